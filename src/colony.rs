@@ -3,21 +3,50 @@
 //! a "colony" is a single unit that manages a collection of one or more rooms. it has complete
 //! control over those rooms, while other colonies might send a creep through a room it does not
 //! control, they will never interfere with actions.
+pub mod body;
+mod construction;
 pub mod inventory;
+pub mod logistics;
 pub mod memory;
+pub mod planner;
+pub mod repair;
+pub mod sources;
+pub mod spawning;
 
 pub use self::inventory::{
-    Inventory, Reservation, ReservationError, ReservationId, Target, TargetInfo,
+    Inventory, Reservation, ReservationError, ReservationId, SelectionPolicy, Target, TargetInfo,
 };
 
 mod room;
 
-use screeps::RoomName;
+use core::fmt;
+use std::collections::HashSet;
+
+use log::{info, warn};
+use screeps::{game, prelude::*, Room, RoomName};
 use serde::{Deserialize, Serialize};
 
-use crate::{colony::room::RoomInfo, state::GlobalState};
+use crate::{
+    colony::{
+        logistics::Logistics,
+        memory::ColonyMemory,
+        repair::RepairState,
+        room::{RoomInfo, RoomKind},
+        sources::SourceAssignments,
+        spawning::SpawnQueue,
+    },
+    state::GlobalState,
+    tasks::ColonyTasks,
+};
+
+/// names given to colonies, in order of creation. once these run out, names are reused with a
+/// numeric suffix.
+const COLONY_NAMES: &[&str] = &[
+    "Alder", "Birch", "Cedar", "Dogwood", "Elm", "Fir", "Ginkgo", "Hazel", "Juniper", "Larch",
+    "Maple", "Oak", "Pine", "Rowan", "Spruce", "Willow", "Yew",
+];
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ColonyId(u32);
 
 impl ColonyId {
@@ -26,19 +55,237 @@ impl ColonyId {
         *next_colony_id = next_colony_id.checked_add(1).expect("u32::MAX colony IDs");
         Self(id)
     }
+
+    /// generates a human readable name for the colony with this id
+    fn generate_name(self) -> String {
+        let idx = self.0 as usize;
+        let base = COLONY_NAMES[idx % COLONY_NAMES.len()];
+        match idx / COLONY_NAMES.len() {
+            0 => String::from(base),
+            generation => format!("{} {}", base, generation + 1),
+        }
+    }
+}
+
+impl fmt::Display for ColonyId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
 }
 
 #[derive(Debug)]
 pub struct Colony {
+    id: ColonyId,
     name: String,
     rooms: Vec<RoomInfo>,
+    /// the names of all creeps that belong to this colony, regardless of which room they are in
+    creeps: HashSet<String>,
+    pub inventory: Inventory,
+    pub tasks: ColonyTasks,
+    pub sources: SourceAssignments,
+    pub repair: RepairState,
+    pub logistics: Logistics,
+    spawn_queue: SpawnQueue,
 }
 
 impl Colony {
     pub fn new(state: &mut GlobalState, base_room: RoomName) -> Self {
+        let id = ColonyId::next(&mut state.memory.next_colony_id);
         Self {
-            name: String::from("uwu"),
-            rooms: todo!(),
+            id,
+            name: id.generate_name(),
+            rooms: vec![RoomInfo::new(base_room, RoomKind::Owned)],
+            creeps: HashSet::new(),
+            inventory: Inventory::default(),
+            tasks: ColonyTasks::default(),
+            sources: SourceAssignments::default(),
+            repair: RepairState::default(),
+            logistics: Logistics::default(),
+            spawn_queue: SpawnQueue::default(),
+        }
+    }
+
+    pub fn from_memory(memory: ColonyMemory) -> Self {
+        Self {
+            id: memory.id,
+            name: memory.name,
+            rooms: memory.rooms,
+            creeps: memory.creeps.into_iter().collect(),
+            inventory: Inventory::default(),
+            tasks: ColonyTasks::default(),
+            sources: SourceAssignments::default(),
+            repair: RepairState {
+                config: memory.repair,
+                ..Default::default()
+            },
+            logistics: Logistics::default(),
+            spawn_queue: SpawnQueue::default(),
+        }
+    }
+
+    pub fn to_memory(&self) -> ColonyMemory {
+        ColonyMemory {
+            id: self.id,
+            name: self.name.clone(),
+            rooms: self.rooms.clone(),
+            creeps: self.creeps.iter().cloned().collect(),
+            repair: self.repair.config.clone(),
         }
     }
+
+    pub fn id(&self) -> ColonyId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn rooms(&self) -> &[RoomInfo] {
+        &self.rooms
+    }
+
+    /// whether this colony contains the room `name` as one of its owned base rooms
+    pub fn owns_room(&self, name: RoomName) -> bool {
+        self.rooms
+            .iter()
+            .any(|room| room.name() == name && matches!(room.kind(), RoomKind::Owned))
+    }
+
+    /// whether the room `name` is any kind of room in this colony
+    pub fn contains_room(&self, name: RoomName) -> bool {
+        self.rooms.iter().any(|room| room.name() == name)
+    }
+
+    /// the name of the main owned room of this colony
+    pub fn base_room_name(&self) -> RoomName {
+        // colonies are always created with an owned room and are removed if they lose it
+        self.rooms
+            .iter()
+            .find(|room| matches!(room.kind(), RoomKind::Owned))
+            .map(RoomInfo::name)
+            .unwrap()
+    }
+
+    /// all rooms in this colony that are currently visible
+    pub fn visible_rooms(&self) -> Vec<Room> {
+        let rooms = game::rooms();
+        self.rooms
+            .iter()
+            .filter_map(|room| rooms.get(room.name()))
+            .collect()
+    }
+
+    pub fn creeps(&self) -> &HashSet<String> {
+        &self.creeps
+    }
+
+    /// rescans all visible rooms in the colony for resources that can be used
+    pub fn update_inventory(&mut self) {
+        let rooms = self.visible_rooms();
+        self.inventory.update_targets(&rooms);
+    }
+
+    /// rescans all visible rooms in the colony for sources and frees the slots of dead harvesters
+    pub fn update_sources(&mut self) {
+        let rooms = self.visible_rooms();
+        self.sources.update(&rooms);
+    }
+}
+
+/// restores colonies from memory, then creates a new colony for every owned room that is not
+/// already the base room of a colony.
+pub fn init_colonies(state: &mut GlobalState) {
+    for memory in state.memory.colonies.drain(..) {
+        let colony = Colony::from_memory(memory);
+        state.colonies.insert(colony.id(), colony);
+    }
+
+    // owned rooms are always visible, so any colony without a visible owned room has been lost
+    state.colonies.retain(|_, colony| {
+        let alive = colony.rooms().iter().any(|room| {
+            matches!(room.kind(), RoomKind::Owned)
+                && game::rooms()
+                    .get(room.name())
+                    .and_then(|room| room.controller())
+                    .is_some_and(|controller| controller.my())
+        });
+        if !alive {
+            info!(
+                "colony {} {} no longer owns a room",
+                colony.name(),
+                colony.id()
+            );
+        }
+        alive
+    });
+
+    for room in game::rooms().values() {
+        let owned = room.controller().is_some_and(|controller| controller.my());
+        if !owned {
+            continue;
+        }
+
+        let name = room.name();
+        if state.colonies.values().any(|colony| colony.owns_room(name)) {
+            continue;
+        }
+
+        let colony = Colony::new(state, name);
+        info!(
+            "created colony {} {} with base room {}",
+            colony.name(),
+            colony.id(),
+            name
+        );
+        state.colonies.insert(colony.id(), colony);
+    }
+}
+
+/// removes dead creeps from their colonies and assigns every creep that does not have a colony to
+/// the colony that contains its current room, or the closest colony if there is none.
+pub fn assign_creeps(state: &mut GlobalState) {
+    let creeps = game::creeps();
+    for colony in state.colonies.values_mut() {
+        colony
+            .creeps
+            .retain(|name| creeps.get(name.clone()).is_some());
+    }
+
+    let assigned = state
+        .colonies
+        .values()
+        .flat_map(|colony| colony.creeps.iter().cloned())
+        .collect::<HashSet<_>>();
+
+    for creep in creeps.values() {
+        if !creep.my() {
+            continue;
+        }
+
+        let name = creep.name();
+        if assigned.contains(&name) {
+            continue;
+        }
+
+        let room_name = creep.pos().room_name();
+        let colony_id = state
+            .colonies
+            .values()
+            .find(|colony| colony.contains_room(room_name))
+            .or_else(|| {
+                state.colonies.values().min_by_key(|colony| {
+                    game::map::get_room_linear_distance(colony.base_room_name(), room_name, false)
+                })
+            })
+            .map(Colony::id);
+
+        let Some(colony) = colony_id.and_then(|id| state.colonies.get_mut(&id)) else {
+            warn!("no colony to assign creep {} to", name);
+            continue;
+        };
+
+        info!("assigning creep {} to colony {}", name, colony.name());
+        colony.creeps.insert(name);
+    }
 }
@@ -0,0 +1,149 @@
+//! designs creep bodies for each role from an energy budget
+//!
+//! everything in here is pure, bodies only depend on the arguments passed in and never on the
+//! game state.
+
+use screeps::{Part, CARRY_CAPACITY, ENERGY_REGEN_TIME, HARVEST_POWER, MAX_CREEP_SIZE};
+
+/// the kind of terrain a creep will spend most of its time travelling over, used to determine how
+/// many MOVE parts it needs to move at full speed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Travel {
+    Road,
+    Plain,
+}
+
+impl Travel {
+    /// the number of MOVE parts needed for `weight` fatigue generating parts to move one tile
+    /// every tick
+    pub fn moves_for(self, weight: u32) -> u32 {
+        match self {
+            // roads generate 1 fatigue per part and each MOVE removes 2
+            Travel::Road => weight.div_ceil(2),
+            // plains generate 2 fatigue per part
+            Travel::Plain => weight,
+        }
+    }
+}
+
+/// a drop harvester with exactly enough WORK to drain a source with `source_capacity` energy in a
+/// single regen cycle, or as much WORK as `budget` allows.
+pub fn drop_harvester(budget: u32, source_capacity: u32, travel: Travel) -> Vec<Part> {
+    let work = source_capacity.div_ceil(ENERGY_REGEN_TIME * HARVEST_POWER);
+    scale(&[Part::Work], work, budget, travel)
+}
+
+/// a hauler with enough CARRY to move `income` energy per tick from a source `distance` tiles
+/// away, or as much CARRY as `budget` allows.
+pub fn hauler(budget: u32, distance: u32, income: u32, travel: Travel) -> Vec<Part> {
+    // the hauler needs to carry everything produced during a round trip
+    let round_trip = distance * 2;
+    let carry = (income * round_trip).div_ceil(CARRY_CAPACITY).max(1);
+    scale(&[Part::Carry], carry, budget, travel)
+}
+
+/// a general purpose worker with an equal amount of WORK and CARRY, as large as `budget` allows
+pub fn worker(budget: u32, travel: Travel) -> Vec<Part> {
+    scale(&[Part::Work, Part::Carry], u32::MAX, budget, travel)
+}
+
+/// a scout, which only needs to move
+pub fn scout() -> Vec<Part> {
+    vec![Part::Move]
+}
+
+/// the total energy cost of `body`
+pub fn cost(body: &[Part]) -> u32 {
+    body.iter().map(|part| part.cost()).sum()
+}
+
+/// repeats `unit` up to `max_units` times, adding enough MOVE parts to travel at full speed over
+/// `travel`, while staying within `budget` and the creep size limit.
+///
+/// returns an empty body if not even a single unit can be afforded.
+fn scale(unit: &[Part], max_units: u32, budget: u32, travel: Travel) -> Vec<Part> {
+    let unit_len = unit.len() as u32;
+    let unit_cost = cost(unit);
+    let move_cost = Part::Move.cost();
+
+    let fits = |units: u32| {
+        let parts = units * unit_len;
+        let moves = travel.moves_for(parts);
+        parts + moves <= MAX_CREEP_SIZE && units * unit_cost + moves * move_cost <= budget
+    };
+
+    // the creep size limit keeps this small, so just count up
+    let mut units = 0;
+    while units < max_units && fits(units + 1) {
+        units += 1;
+    }
+
+    if units == 0 {
+        return Vec::new();
+    }
+
+    let moves = travel.moves_for(units * unit_len);
+    let mut body = Vec::with_capacity((units * unit_len + moves) as usize);
+    for part in unit {
+        body.extend((0..units).map(|_| *part));
+    }
+    body.extend((0..moves).map(|_| Part::Move));
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(body: &[Part], part: Part) -> usize {
+        body.iter().filter(|&&p| p == part).count()
+    }
+
+    #[test]
+    fn bodies_are_capped_at_the_creep_size_limit() {
+        let body = worker(100_000, Travel::Road);
+        assert_eq!(body.len(), 48);
+        assert_eq!(count(&body, Part::Work), 16);
+
+        // one MOVE for every two WORK on roads, 33 + 17 = 50
+        let body = drop_harvester(100_000, 100_000, Travel::Road);
+        assert_eq!(body.len(), 50);
+        assert_eq!(count(&body, Part::Work), 33);
+        assert_eq!(count(&body, Part::Move), 17);
+    }
+
+    #[test]
+    fn plains_need_more_move_parts_than_roads() {
+        // 10 energy per tick over a 100 tile round trip needs 20 CARRY
+        let road = hauler(10_000, 50, 10, Travel::Road);
+        assert_eq!(count(&road, Part::Carry), 20);
+        assert_eq!(count(&road, Part::Move), 10);
+
+        let plain = hauler(10_000, 50, 10, Travel::Plain);
+        assert_eq!(count(&plain, Part::Carry), 20);
+        assert_eq!(count(&plain, Part::Move), 20);
+    }
+
+    #[test]
+    fn scaling_stops_at_the_budget() {
+        // each unit costs 200 with its MOVE, a third one doesn't fit
+        let body = worker(550, Travel::Road);
+        assert_eq!(
+            body,
+            vec![
+                Part::Work,
+                Part::Work,
+                Part::Carry,
+                Part::Carry,
+                Part::Move,
+                Part::Move,
+            ]
+        );
+        assert!(cost(&body) <= 550);
+
+        let body = drop_harvester(300, 3000, Travel::Road);
+        assert_eq!(body, vec![Part::Work, Part::Work, Part::Move]);
+
+        assert!(worker(100, Travel::Road).is_empty());
+    }
+}
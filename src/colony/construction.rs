@@ -0,0 +1,134 @@
+//! turns the plans of owned rooms into construction sites as the controller level unlocks them
+
+use std::collections::HashSet;
+
+use log::{debug, info, warn};
+use screeps::{find, game, prelude::*, ErrorCode, Room};
+
+use crate::{
+    colony::{
+        planner::{self, PlanInput, RoomPlan},
+        room::RoomKind,
+        Colony,
+    },
+    movement,
+    util::Tile,
+};
+
+/// how often rooms are planned and construction sites are placed
+const CONSTRUCTION_INTERVAL: u32 = 50;
+
+/// the most construction sites that are kept in a single room at once, so that builders finish
+/// things instead of spreading energy across everything
+const MAX_SITES_PER_ROOM: usize = 5;
+
+impl Colony {
+    /// plans owned rooms that do not have an up to date plan, and places construction sites for
+    /// the parts of existing plans that have been unlocked
+    pub fn update_construction(&mut self) {
+        if game::time() % CONSTRUCTION_INTERVAL != 0 {
+            return;
+        }
+
+        let rooms = game::rooms();
+        for info in self.rooms.iter_mut() {
+            if !matches!(info.kind(), RoomKind::Owned) {
+                continue;
+            }
+            let Some(room) = rooms.get(info.name()) else {
+                continue;
+            };
+
+            let current = info
+                .plan()
+                .filter(|plan| plan.version == planner::PLAN_VERSION);
+            let Some(plan) = current else {
+                match PlanInput::from_room(&room).and_then(|input| planner::plan_room(&input)) {
+                    Ok(plan) => {
+                        info!(
+                            "planned {} structures in {} around {}",
+                            plan.structures.len(),
+                            info.name(),
+                            plan.center
+                        );
+                        info.set_plan(plan);
+                    }
+                    Err(e) => warn!("unable to plan {}: {}", info.name(), e),
+                }
+                // planning is expensive, wait to place sites until the next interval
+                continue;
+            };
+
+            place_sites(&room, plan);
+        }
+    }
+}
+
+/// places construction sites for unlocked structures in `plan` that have not been built yet
+fn place_sites(room: &Room, plan: &RoomPlan) {
+    let Some(controller) = room.controller() else {
+        return;
+    };
+    let rcl = controller.level();
+
+    let sites = room.find(find::MY_CONSTRUCTION_SITES, None);
+    let mut budget = MAX_SITES_PER_ROOM.saturating_sub(sites.len());
+    if budget == 0 {
+        return;
+    }
+
+    let mut built = room
+        .find(find::STRUCTURES, None)
+        .iter()
+        .map(|structure| (Tile::from(structure.pos()), structure.structure_type()))
+        .collect::<HashSet<_>>();
+    built.extend(
+        sites
+            .iter()
+            .map(|site| (Tile::from(site.pos()), site.structure_type())),
+    );
+
+    let mut placed = false;
+    for structure in plan.unlocked(rcl) {
+        if budget == 0 {
+            break;
+        }
+        if built.contains(&(structure.tile, structure.structure_type)) {
+            continue;
+        }
+
+        let Tile { x, y } = structure.tile;
+        match room.create_construction_site(x, y, structure.structure_type, None) {
+            Ok(()) => {
+                debug!(
+                    "placed {:?} site at {} in {}",
+                    structure.structure_type,
+                    structure.tile,
+                    room.name()
+                );
+                budget -= 1;
+                placed = true;
+            }
+            // structures over the limit and the global site limit clear up on their own
+            Err(e @ (ErrorCode::RclNotEnough | ErrorCode::Full)) => debug!(
+                "unable to place {:?} site at {} in {}: {:?}",
+                structure.structure_type,
+                structure.tile,
+                room.name(),
+                e
+            ),
+            Err(e) => warn!(
+                "unable to place {:?} site at {} in {}: {:?}",
+                structure.structure_type,
+                structure.tile,
+                room.name(),
+                e
+            ),
+        }
+    }
+
+    // new sites block tiles, so paths through the room need to know about them
+    if placed {
+        movement::costs::invalidate(room.name());
+    }
+}
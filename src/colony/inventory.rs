@@ -1,90 +1,422 @@
 use core::sync::atomic::{self, AtomicU32};
-use std::collections::{HashMap, HashSet};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+};
 
-use log::warn;
-use screeps::{find, HasId, ObjectId, Resource, ResourceType, Room, StructureStorage};
+use log::{debug, warn};
+use screeps::{
+    find, prelude::*, Creep, ObjectId, Position, RawObjectId, Resource, ResourceType, Room, Ruin,
+    Store, StructureContainer, StructureLink, StructureObject, StructureStorage, StructureTerminal,
+    Tombstone,
+};
 use serde::{Deserialize, Serialize};
 
+/// how far from the controller a container can be to count as the controller's container
+pub const CONTROLLER_CONTAINER_RANGE: u32 = 3;
+
+/// how many ticks a reservation can be held before it is assumed to be stuck and released
+const RESERVATION_TTL: u32 = 500;
+
 #[derive(Debug, Default)]
 pub struct Inventory {
     /// all possible known targets that could serve a request
     targets: HashMap<Target, TargetInfo>,
     /// all the reservations that this inventory knows about
     reservations: HashMap<ReservationId, Reservation>,
+    /// resources that creeps are on their way to deposit into sinks, keyed by the same ids as
+    /// `reservations`
+    deliveries: HashMap<ReservationId, Delivery>,
+    /// the total amount of each resource type on its way to each sink
+    incoming: HashMap<RawObjectId, HashMap<ResourceType, u32>>,
+    /// reservations and deliveries released by [`Inventory::sweep_reservations`] that their
+    /// tasks may still release, and the tick they were released on
+    expired: HashMap<ReservationId, u32>,
 }
 
 impl Inventory {
+    /// reserves `amount` of resource `kind` from a single target for `owner`, using `policy` to
+    /// choose between the targets that have enough for a requester at `from`
     pub fn request(
         &mut self,
+        owner: ObjectId<Creep>,
         kind: ResourceType,
         amount: u32,
+        from: Position,
+        policy: SelectionPolicy,
+        now: u32,
     ) -> Result<ReservationId, ReservationError> {
-        // find a suitable target
-        let target: Option<(&Target, &mut TargetInfo)> =
-            self.targets.iter_mut().find(|(target, info)| {
-                // TODO: make multiple reservations per target allowed
-                if info.reservations.len() > 0 {
-                    return false;
-                }
+        let candidates = self.candidates(kind);
+        if candidates.is_empty() {
+            return Err(ReservationError::NoTarget(kind));
+        }
 
-                target
-                    .get_available_amount(kind)
-                    .is_some_and(|avl| avl > amount)
-            });
+        match select(candidates, from, amount, policy) {
+            Some(target) => Ok(self.reserve(target, owner, kind, amount, now)),
+            None => Err(ReservationError::NotEnough(NotEnoughErr { kind, amount })),
+        }
+    }
 
-        if let Some((&target, info)) = target {
-            let id = ReservationId::next();
-            info.reservations.insert(id);
+    /// like [`Inventory::request`], but if no single target has `amount` available, reserves
+    /// whatever is available in the best target instead. the reservation may be for less than
+    /// `amount`.
+    pub fn request_partial(
+        &mut self,
+        owner: ObjectId<Creep>,
+        kind: ResourceType,
+        amount: u32,
+        from: Position,
+        policy: SelectionPolicy,
+        now: u32,
+    ) -> Result<ReservationId, ReservationError> {
+        let candidates = self.candidates(kind);
+        let target = select(candidates.iter().copied(), from, amount, policy)
+            .or_else(|| select(candidates.iter().copied(), from, 1, policy))
+            .ok_or(ReservationError::NoTarget(kind))?;
 
-            let reservation = Reservation {
-                target,
-                kind,
-                amount,
+        let available = candidates
+            .iter()
+            .find(|candidate| candidate.target == target)
+            .map_or(0, |candidate| candidate.available);
+        Ok(self.reserve(target, owner, kind, amount.min(available), now))
+    }
+
+    /// reserves `amount` of resource `kind` from `target` for `owner`
+    pub fn request_from(
+        &mut self,
+        owner: ObjectId<Creep>,
+        target: Target,
+        kind: ResourceType,
+        amount: u32,
+        now: u32,
+    ) -> Result<ReservationId, ReservationError> {
+        let Some(info) = self.targets.get(&target) else {
+            return Err(ReservationError::NoTarget(kind));
+        };
+        if info.available_amount(kind) < amount {
+            return Err(ReservationError::NotEnough(NotEnoughErr { kind, amount }));
+        }
+
+        Ok(self.reserve(target, owner, kind, amount, now))
+    }
+
+    /// reserves up to `amount` of resource `kind` split across as many targets as needed. each
+    /// target is chosen with `policy` from the position of the previous one, starting at `from`,
+    /// so the reservations are in the order they should be picked up in. the total may be less
+    /// than `amount` if there is not enough available.
+    pub fn request_split(
+        &mut self,
+        owner: ObjectId<Creep>,
+        kind: ResourceType,
+        amount: u32,
+        from: Position,
+        policy: SelectionPolicy,
+        now: u32,
+    ) -> Result<Vec<ReservationId>, ReservationError> {
+        let mut candidates = self.candidates(kind);
+        let mut reservations = Vec::new();
+        let mut remaining = amount;
+        let mut pos = from;
+        while remaining > 0 {
+            let Some(target) = select(candidates.iter().copied(), pos, 1, policy) else {
+                break;
             };
-            self.reservations.insert(id, reservation);
+            let idx = candidates
+                .iter()
+                .position(|candidate| candidate.target == target)
+                .unwrap();
+            let candidate = candidates.swap_remove(idx);
+
+            let reserved = remaining.min(candidate.available);
+            reservations.push(self.reserve(target, owner, kind, reserved, now));
+            remaining -= reserved;
+            pos = candidate.pos;
+        }
 
-            Ok(id)
+        if reservations.is_empty() {
+            Err(ReservationError::NoTarget(kind))
         } else {
-            Err(ReservationError::NotEnough(NotEnoughErr { kind, amount }))
+            Ok(reservations)
         }
     }
 
+    /// every known target that has some of resource `kind` that isn't reserved
+    pub fn candidates(&self, kind: ResourceType) -> Vec<Candidate<Target>> {
+        self.targets
+            .iter()
+            .map(|(&target, info)| Candidate {
+                target,
+                pos: info.pos,
+                available: info.available_amount(kind),
+            })
+            .filter(|candidate| candidate.available > 0)
+            .collect()
+    }
+
+    /// reserves resources from `target`, which must be a known target
+    fn reserve(
+        &mut self,
+        target: Target,
+        owner: ObjectId<Creep>,
+        kind: ResourceType,
+        amount: u32,
+        now: u32,
+    ) -> ReservationId {
+        let id = ReservationId::next();
+        self.targets
+            .get_mut(&target)
+            .unwrap()
+            .reserve(id, kind, amount);
+
+        let reservation = Reservation {
+            target,
+            kind,
+            amount,
+            owner,
+            created: now,
+        };
+        self.reservations.insert(id, reservation);
+
+        id
+    }
+
+    /// reserves room for `amount` of resource `kind` in `sink`, so that other creeps don't try to
+    /// fill the same room
+    pub fn reserve_delivery(
+        &mut self,
+        owner: ObjectId<Creep>,
+        sink: RawObjectId,
+        kind: ResourceType,
+        amount: u32,
+        now: u32,
+    ) -> ReservationId {
+        let id = ReservationId::next();
+        *self
+            .incoming
+            .entry(sink)
+            .or_default()
+            .entry(kind)
+            .or_default() += amount;
+        self.deliveries.insert(
+            id,
+            Delivery {
+                sink,
+                kind,
+                amount,
+                owner,
+                created: now,
+            },
+        );
+        id
+    }
+
+    /// the amount of resource `kind` that creeps are on their way to deposit into `sink`
+    pub fn incoming_amount(&self, sink: RawObjectId, kind: ResourceType) -> u32 {
+        self.incoming
+            .get(&sink)
+            .and_then(|incoming| incoming.get(&kind))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// releases a reservation or a delivery
     pub fn release(&mut self, id: ReservationId) {
-        let Some(Reservation { target, .. }) = self.reservations.get(&id) else {
-            warn!("reservation {:?} did not exist", id);
+        if let Some(delivery) = self.deliveries.remove(&id) {
+            self.remove_incoming(&delivery);
+            return;
+        }
+
+        let Some(reservation) = self.reservations.remove(&id) else {
+            // the task holding an expired reservation doesn't know about it until it releases it
+            if self.expired.remove(&id).is_none() {
+                warn!("reservation {:?} did not exist", id);
+            }
             return;
         };
-        let Some(target_info) = self.targets.get_mut(&target) else {
+        let Some(target_info) = self.targets.get_mut(&reservation.target) else {
             warn!("reservation {:?} had invalid target", id);
             return;
         };
 
-        target_info.reservations.remove(&id);
-        self.reservations.remove(&id);
+        target_info.unreserve(id, reservation.kind, reservation.amount);
     }
 
-    pub fn update_targets(&mut self, room: &Room) {
-        // TODO: scan more than resources
+    /// releases reservations and deliveries whose owner has died, whose target is gone, or that
+    /// have been held for longer than [`RESERVATION_TTL`]
+    pub fn sweep_reservations(&mut self, now: u32) {
+        let expired = self
+            .reservations
+            .iter()
+            .filter(|(id, reservation)| {
+                if reservation.owner.resolve().is_none() {
+                    debug!("releasing reservation {:?} of dead creep", id);
+                } else if !self.targets.contains_key(&reservation.target) {
+                    debug!("releasing reservation {:?} with missing target", id);
+                } else if now.saturating_sub(reservation.created) > RESERVATION_TTL {
+                    warn!(
+                        "releasing reservation {:?} held by {} for too long",
+                        id, reservation.owner
+                    );
+                } else {
+                    return false;
+                }
+                true
+            })
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+
+        for id in expired {
+            self.expire(id, now);
+        }
+
+        let expired = self
+            .deliveries
+            .iter()
+            .filter(|(id, delivery)| {
+                if delivery.owner.resolve().is_none() {
+                    debug!("releasing delivery {:?} of dead creep", id);
+                } else if now.saturating_sub(delivery.created) > RESERVATION_TTL {
+                    warn!(
+                        "releasing delivery {:?} held by {} for too long",
+                        id, delivery.owner
+                    );
+                } else {
+                    return false;
+                }
+                true
+            })
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+
+        for id in expired {
+            self.expire(id, now);
+        }
+
+        // tasks that are still running release their ids well within the TTL
+        self.expired
+            .retain(|_, expired| now.saturating_sub(*expired) <= RESERVATION_TTL);
+    }
+
+    /// releases a reservation or delivery without its task, so that the task releasing it later
+    /// is not mistaken for releasing an id that never existed
+    fn expire(&mut self, id: ReservationId, now: u32) {
+        if let Some(delivery) = self.deliveries.remove(&id) {
+            self.remove_incoming(&delivery);
+        } else if let Some(reservation) = self.reservations.remove(&id) {
+            // release warns about missing targets, which are expected here
+            if let Some(info) = self.targets.get_mut(&reservation.target) {
+                info.unreserve(id, reservation.kind, reservation.amount);
+            }
+        } else {
+            return;
+        }
+        self.expired.insert(id, now);
+    }
+
+    fn remove_incoming(&mut self, delivery: &Delivery) {
+        let Some(incoming) = self.incoming.get_mut(&delivery.sink) else {
+            return;
+        };
+        if let Some(amount) = incoming.get_mut(&delivery.kind) {
+            *amount = amount.saturating_sub(delivery.amount);
+            if *amount == 0 {
+                incoming.remove(&delivery.kind);
+            }
+        }
+        if incoming.is_empty() {
+            self.incoming.remove(&delivery.sink);
+        }
+    }
+
+    /// rescans `rooms` for targets, adding new targets and removing ones that no longer exist,
+    /// and records what every target holds this tick
+    pub fn update_targets(&mut self, rooms: &[Room]) {
         let mut seen = HashSet::<Target>::new();
-        for resource in room.find(find::DROPPED_RESOURCES, None) {
-            let target = Target::from(resource.id());
+        for (target, pos) in rooms.iter().flat_map(Self::scan_room) {
             seen.insert(target);
 
             // insert targets that don't yet exist
-            if !self.targets.contains_key(&target) {
-                self.targets.insert(
-                    target,
-                    TargetInfo {
-                        reservations: HashSet::new(),
-                    },
-                );
-            }
+            self.targets
+                .entry(target)
+                .or_insert_with(|| TargetInfo::new(pos))
+                .amounts = target.amounts();
         }
 
         // clear out targets that no longer exist
         self.targets.retain(|target, _| seen.contains(target));
     }
 
+    /// finds every target in `room` and where it is
+    fn scan_room(room: &Room) -> Vec<(Target, Position)> {
+        let mut targets = Vec::new();
+        targets.extend(
+            room.find(find::DROPPED_RESOURCES, None)
+                .iter()
+                .map(|resource| (Target::from(resource.id()), resource.pos())),
+        );
+        targets.extend(
+            room.find(find::TOMBSTONES, None)
+                .iter()
+                .map(|tombstone| (Target::from(tombstone.id()), tombstone.pos())),
+        );
+        targets.extend(
+            room.find(find::RUINS, None)
+                .iter()
+                .map(|ruin| (Target::from(ruin.id()), ruin.pos())),
+        );
+
+        let sources = room.find(find::SOURCES, None);
+        let controller = room.controller();
+        for structure in room.find(find::STRUCTURES, None) {
+            let pos = structure.pos();
+            let target = match structure {
+                // other containers are usually used as sinks, taking from them would just move
+                // resources back and forth
+                StructureObject::StructureContainer(container) => {
+                    let at_source = sources.iter().any(|source| pos.is_near_to(source.pos()));
+                    let at_controller = controller.as_ref().is_some_and(|controller| {
+                        pos.in_range_to(controller.pos(), CONTROLLER_CONTAINER_RANGE)
+                    });
+                    (at_source || at_controller).then(|| Target::from(container.id()))
+                }
+                StructureObject::StructureStorage(storage) if storage.my() => {
+                    Some(Target::from(storage.id()))
+                }
+                StructureObject::StructureTerminal(terminal) if terminal.my() => {
+                    Some(Target::from(terminal.id()))
+                }
+                StructureObject::StructureLink(link) if link.my() => Some(Target::from(link.id())),
+                _ => None,
+            };
+            targets.extend(target.map(|target| (target, pos)));
+        }
+
+        targets
+    }
+
+    /// the total amount of resource `kind` lying around in drops, tombstones and ruins, which
+    /// will decay if nobody picks it up
+    pub fn loose_amount(&self, kind: ResourceType) -> u32 {
+        self.targets
+            .iter()
+            .filter(|(target, _)| {
+                matches!(
+                    target,
+                    Target::Resource(_) | Target::Tombstone(_) | Target::Ruin(_)
+                )
+            })
+            .map(|(_, info)| info.total_amount(kind))
+            .sum()
+    }
+
+    /// the amount of resource `kind` in all known targets that has not been reserved yet
+    pub fn available_amount(&self, kind: ResourceType) -> u32 {
+        self.targets
+            .values()
+            .map(|info| info.available_amount(kind))
+            .sum()
+    }
+
     // TODO: differentiate between "no reservation" and "no target?" in a result?
     pub fn resolve_reservation(&self, id: &ReservationId) -> Option<&Reservation> {
         let reservation = self.reservations.get(id)?;
@@ -97,6 +429,68 @@ impl Inventory {
     }
 }
 
+/// how a request chooses between the targets that have enough resources to fill it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionPolicy {
+    /// the closest target, so that the requester travels as little as possible
+    #[default]
+    Nearest,
+    /// the target with the most unreserved resources, so that small piles are left alone
+    Fullest,
+    /// the closest target, but targets within [`BALANCED_SLACK`] tiles of the closest one are
+    /// treated as equally close and the fullest of them is picked
+    Balanced,
+}
+
+/// how much further than the closest target a target can be while still being considered by
+/// [`SelectionPolicy::Balanced`]
+pub const BALANCED_SLACK: u32 = 5;
+
+/// a target that could serve a request, as seen by [`select`]
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<T> {
+    pub target: T,
+    pub pos: Position,
+    /// the unreserved amount of the requested resource in the target
+    pub available: u32,
+}
+
+/// picks the target that should serve a request for `amount` resources by a requester at `from`,
+/// out of the candidates that have at least `amount` available. ties are broken by position, so
+/// the result does not depend on the order of `candidates`.
+pub fn select<T>(
+    candidates: impl IntoIterator<Item = Candidate<T>>,
+    from: Position,
+    amount: u32,
+    policy: SelectionPolicy,
+) -> Option<T> {
+    let candidates = candidates
+        .into_iter()
+        .filter(|candidate| candidate.available >= amount)
+        .map(|candidate| (from.get_range_to(candidate.pos), candidate))
+        .collect::<Vec<_>>();
+    let tiebreak = |candidate: &Candidate<T>| candidate.pos.packed_repr();
+
+    let best = match policy {
+        SelectionPolicy::Nearest => candidates.into_iter().min_by_key(|(range, candidate)| {
+            (*range, Reverse(candidate.available), tiebreak(candidate))
+        }),
+        SelectionPolicy::Fullest => candidates.into_iter().min_by_key(|(range, candidate)| {
+            (Reverse(candidate.available), *range, tiebreak(candidate))
+        }),
+        SelectionPolicy::Balanced => {
+            let closest = candidates.iter().map(|(range, _)| *range).min()?;
+            candidates
+                .into_iter()
+                .filter(|(range, _)| *range <= closest + BALANCED_SLACK)
+                .min_by_key(|(range, candidate)| {
+                    (Reverse(candidate.available), *range, tiebreak(candidate))
+                })
+        }
+    };
+    best.map(|(_, candidate)| candidate.target)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct ReservationId(u32);
 
@@ -114,7 +508,66 @@ impl ReservationId {
 /// describes the current state of a single target, namely all active reservations
 #[derive(Debug)]
 pub struct TargetInfo {
+    /// where the target is, targets never move so this is only looked up once
+    pos: Position,
+    /// the amount of each resource type in the target at the start of the tick. stores only
+    /// change between ticks, so this is the same as looking the target up.
+    amounts: HashMap<ResourceType, u32>,
     reservations: HashSet<ReservationId>,
+    /// the total amount of each resource type held by `reservations`
+    reserved: HashMap<ResourceType, u32>,
+}
+
+impl TargetInfo {
+    fn new(pos: Position) -> Self {
+        Self {
+            pos,
+            amounts: HashMap::new(),
+            reservations: HashSet::new(),
+            reserved: HashMap::new(),
+        }
+    }
+
+    fn reserve(&mut self, id: ReservationId, kind: ResourceType, amount: u32) {
+        if self.reservations.insert(id) {
+            *self.reserved.entry(kind).or_default() += amount;
+        }
+    }
+
+    fn unreserve(&mut self, id: ReservationId, kind: ResourceType, amount: u32) {
+        if !self.reservations.remove(&id) {
+            return;
+        }
+        if let Some(reserved) = self.reserved.get_mut(&kind) {
+            *reserved = reserved.saturating_sub(amount);
+            if *reserved == 0 {
+                self.reserved.remove(&kind);
+            }
+        }
+    }
+
+    pub fn pos(&self) -> Position {
+        self.pos
+    }
+
+    /// the amount of resource `kind` held by reservations on this target
+    pub fn reserved_amount(&self, kind: ResourceType) -> u32 {
+        self.reserved.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// the total amount of resource `kind` in the target
+    ///
+    /// **WARNING** this does not account for reservations, use [`TargetInfo::available_amount`]
+    /// to get the amount that can still be reserved
+    pub fn total_amount(&self, kind: ResourceType) -> u32 {
+        self.amounts.get(&kind).copied().unwrap_or(0)
+    }
+
+    /// the amount of resource `kind` in the target that has not been reserved yet
+    pub fn available_amount(&self, kind: ResourceType) -> u32 {
+        self.total_amount(kind)
+            .saturating_sub(self.reserved_amount(kind))
+    }
 }
 
 #[derive(Debug)]
@@ -122,6 +575,10 @@ pub struct Reservation {
     target: Target,
     kind: ResourceType,
     amount: u32,
+    /// the creep that will pick up the resources
+    owner: ObjectId<Creep>,
+    /// the tick the reservation was made on
+    created: u32,
 }
 
 impl Reservation {
@@ -136,13 +593,35 @@ impl Reservation {
     pub fn amount(&self) -> u32 {
         self.amount
     }
+
+    pub fn owner(&self) -> ObjectId<Creep> {
+        self.owner
+    }
+
+    pub fn created(&self) -> u32 {
+        self.created
+    }
+}
+
+/// room reserved in a sink for resources that a creep is bringing to it
+#[derive(Debug)]
+pub struct Delivery {
+    sink: RawObjectId,
+    kind: ResourceType,
+    amount: u32,
+    /// the creep that is bringing the resources
+    owner: ObjectId<Creep>,
+    /// the tick the delivery was reserved on
+    created: u32,
 }
 
 #[derive(Debug, Clone, Copy)]
 /// describes the way in which a request for resources failed
 pub enum ReservationError {
+    /// no tracked target has any unreserved resources of this type
+    NoTarget(ResourceType),
     /// the request for a resource could not be filled because there was not enough of this
-    /// resource in any tracked target
+    /// resource in any single tracked target
     NotEnough(NotEnoughErr),
 }
 
@@ -155,26 +634,48 @@ pub struct NotEnoughErr {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Target {
     Resource(ObjectId<Resource>),
+    /// a container next to a source or the controller
+    Container(ObjectId<StructureContainer>),
     Storage(ObjectId<StructureStorage>),
-    // TODO: containers (but make sure to only add source ones)
+    Terminal(ObjectId<StructureTerminal>),
+    Link(ObjectId<StructureLink>),
+    Tombstone(ObjectId<Tombstone>),
+    Ruin(ObjectId<Ruin>),
 }
 
 impl Target {
-    /// gets the available abount of resource `kind` in this target, if the target can be found
-    ///
-    /// **WARNING** this does not account for reservations, this is the **total** amount
-    pub fn get_available_amount(&self, kind: ResourceType) -> Option<u32> {
+    /// gets the amount of each resource type in this target, which is empty if the target can't
+    /// be found
+    fn amounts(&self) -> HashMap<ResourceType, u32> {
         match self {
-            Target::Resource(id) => id.resolve().map(|resource| {
-                if resource.resource_type() == kind {
-                    resource.amount()
-                } else {
-                    0
-                }
-            }),
-            Target::Storage(id) => id
+            Target::Resource(id) => id
                 .resolve()
-                .map(|storage| storage.store().get_used_capacity(Some(kind))),
+                .map(|resource| (resource.resource_type(), resource.amount()))
+                .into_iter()
+                .collect(),
+            _ => self
+                .store()
+                .map(|store| {
+                    store
+                        .store_types()
+                        .into_iter()
+                        .map(|kind| (kind, store.get_used_capacity(Some(kind))))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    /// gets the store of this target, if it has one and can be found
+    fn store(&self) -> Option<Store> {
+        match self {
+            Target::Resource(_) => None,
+            Target::Container(id) => id.resolve().map(|container| container.store()),
+            Target::Storage(id) => id.resolve().map(|storage| storage.store()),
+            Target::Terminal(id) => id.resolve().map(|terminal| terminal.store()),
+            Target::Link(id) => id.resolve().map(|link| link.store()),
+            Target::Tombstone(id) => id.resolve().map(|tombstone| tombstone.store()),
+            Target::Ruin(id) => id.resolve().map(|ruin| ruin.store()),
         }
     }
 }
@@ -190,3 +691,285 @@ impl From<ObjectId<StructureStorage>> for Target {
         Self::Storage(value)
     }
 }
+
+impl From<ObjectId<StructureContainer>> for Target {
+    fn from(value: ObjectId<StructureContainer>) -> Self {
+        Self::Container(value)
+    }
+}
+
+impl From<ObjectId<StructureTerminal>> for Target {
+    fn from(value: ObjectId<StructureTerminal>) -> Self {
+        Self::Terminal(value)
+    }
+}
+
+impl From<ObjectId<StructureLink>> for Target {
+    fn from(value: ObjectId<StructureLink>) -> Self {
+        Self::Link(value)
+    }
+}
+
+impl From<ObjectId<Tombstone>> for Target {
+    fn from(value: ObjectId<Tombstone>) -> Self {
+        Self::Tombstone(value)
+    }
+}
+
+impl From<ObjectId<Ruin>> for Target {
+    fn from(value: ObjectId<Ruin>) -> Self {
+        Self::Ruin(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_pos as pos;
+
+    fn raw_id(n: u32) -> RawObjectId {
+        format!("{:x}", n).parse().unwrap()
+    }
+
+    fn container(n: u32) -> Target {
+        Target::Container(raw_id(n).into())
+    }
+
+    /// an inventory with a container `n` at `x` holding `amount` energy for each entry
+    fn inventory_with(containers: &[(u32, u8, u32)]) -> Inventory {
+        let mut inventory = Inventory::default();
+        for &(n, x, amount) in containers {
+            let mut info = TargetInfo::new(pos(x, 10));
+            info.amounts.insert(ResourceType::Energy, amount);
+            inventory.targets.insert(container(n), info);
+        }
+        inventory
+    }
+
+    fn creep() -> ObjectId<Creep> {
+        raw_id(1).into()
+    }
+
+    fn available(inventory: &Inventory, n: u32) -> u32 {
+        inventory.targets[&container(n)].available_amount(ResourceType::Energy)
+    }
+
+    fn candidate(target: &'static str, x: u8, available: u32) -> Candidate<&'static str> {
+        Candidate {
+            target,
+            pos: pos(x, 10),
+            available,
+        }
+    }
+
+    /// candidates at ranges 1, 2, 6 and 12 from (10, 10)
+    fn candidates() -> Vec<Candidate<&'static str>> {
+        vec![
+            candidate("too small", 11, 20),
+            candidate("near", 12, 100),
+            candidate("in slack", 16, 300),
+            candidate("far", 22, 900),
+        ]
+    }
+
+    #[test]
+    fn nearest_picks_the_closest_with_enough() {
+        let selected = select(candidates(), pos(10, 10), 50, SelectionPolicy::Nearest);
+        assert_eq!(selected, Some("near"));
+    }
+
+    #[test]
+    fn fullest_picks_the_most_available() {
+        let selected = select(candidates(), pos(10, 10), 50, SelectionPolicy::Fullest);
+        assert_eq!(selected, Some("far"));
+    }
+
+    #[test]
+    fn balanced_picks_the_fullest_within_slack() {
+        let selected = select(candidates(), pos(10, 10), 50, SelectionPolicy::Balanced);
+        assert_eq!(selected, Some("in slack"));
+
+        // just outside of the slack, so only the closest one is left
+        let mut candidates = candidates();
+        candidates[2].pos = pos(12 + BALANCED_SLACK as u8 + 1, 10);
+        let selected = select(candidates, pos(10, 10), 50, SelectionPolicy::Balanced);
+        assert_eq!(selected, Some("near"));
+    }
+
+    #[test]
+    fn selection_does_not_depend_on_order() {
+        let tied = vec![candidate("left", 8, 100), candidate("right", 12, 100)];
+        let mut reversed = tied.clone();
+        reversed.reverse();
+
+        for policy in [
+            SelectionPolicy::Nearest,
+            SelectionPolicy::Fullest,
+            SelectionPolicy::Balanced,
+        ] {
+            assert_eq!(
+                select(tied.clone(), pos(10, 10), 50, policy),
+                select(reversed.clone(), pos(10, 10), 50, policy)
+            );
+        }
+    }
+
+    #[test]
+    fn nothing_is_selected_without_enough_available() {
+        let selected = select(candidates(), pos(10, 10), 1000, SelectionPolicy::Balanced);
+        assert_eq!(selected, None);
+    }
+
+    #[test]
+    fn overlapping_reservations_share_a_target() {
+        let mut inventory = inventory_with(&[(10, 12, 500)]);
+        let energy = ResourceType::Energy;
+        let nearest = SelectionPolicy::Nearest;
+
+        inventory
+            .request(creep(), energy, 300, pos(10, 10), nearest, 0)
+            .unwrap();
+        assert_eq!(available(&inventory, 10), 200);
+
+        // the rest of the target is still available, but not the same amount again
+        assert!(matches!(
+            inventory.request(creep(), energy, 300, pos(10, 10), nearest, 0),
+            Err(ReservationError::NotEnough(_))
+        ));
+        inventory
+            .request_from(creep(), container(10), energy, 200, 0)
+            .unwrap();
+        assert_eq!(available(&inventory, 10), 0);
+        assert!(matches!(
+            inventory.request(creep(), energy, 1, pos(10, 10), nearest, 0),
+            Err(ReservationError::NoTarget(_))
+        ));
+    }
+
+    #[test]
+    fn released_reservations_are_available_again() {
+        let mut inventory = inventory_with(&[(10, 12, 500)]);
+        let energy = ResourceType::Energy;
+
+        let first = inventory
+            .request_from(creep(), container(10), energy, 300, 0)
+            .unwrap();
+        let second = inventory
+            .request_from(creep(), container(10), energy, 100, 0)
+            .unwrap();
+        inventory.release(first);
+        assert_eq!(available(&inventory, 10), 400);
+        inventory.release(second);
+        assert_eq!(available(&inventory, 10), 500);
+        assert!(inventory.reservations.is_empty());
+    }
+
+    #[test]
+    fn deliveries_track_incoming_resources() {
+        let mut inventory = Inventory::default();
+        let energy = ResourceType::Energy;
+        let sink = raw_id(20);
+
+        let first = inventory.reserve_delivery(creep(), sink, energy, 100, 0);
+        let second = inventory.reserve_delivery(creep(), sink, energy, 50, 0);
+        assert_eq!(inventory.incoming_amount(sink, energy), 150);
+
+        inventory.release(first);
+        assert_eq!(inventory.incoming_amount(sink, energy), 50);
+        inventory.release(second);
+        assert_eq!(inventory.incoming_amount(sink, energy), 0);
+        assert!(inventory.incoming.is_empty());
+    }
+
+    #[test]
+    fn partial_requests_take_what_is_there() {
+        let mut inventory = inventory_with(&[(10, 12, 100), (11, 40, 80)]);
+        let energy = ResourceType::Energy;
+
+        let id = inventory
+            .request_partial(
+                creep(),
+                energy,
+                300,
+                pos(10, 10),
+                SelectionPolicy::Nearest,
+                7,
+            )
+            .unwrap();
+        let reservation = inventory.resolve_reservation(&id).unwrap();
+        assert_eq!(reservation.target(), container(10));
+        assert_eq!(reservation.amount(), 100);
+        assert_eq!(reservation.created(), 7);
+
+        // a target with enough is still preferred over a closer one
+        let mut inventory = inventory_with(&[(10, 12, 100), (11, 40, 500)]);
+        let id = inventory
+            .request_partial(
+                creep(),
+                energy,
+                300,
+                pos(10, 10),
+                SelectionPolicy::Nearest,
+                7,
+            )
+            .unwrap();
+        assert_eq!(
+            inventory.resolve_reservation(&id).unwrap().target(),
+            container(11)
+        );
+    }
+
+    #[test]
+    fn split_requests_go_from_target_to_target() {
+        let mut inventory = inventory_with(&[(10, 40, 500), (11, 12, 100), (12, 20, 150)]);
+        let energy = ResourceType::Energy;
+
+        let ids = inventory
+            .request_split(
+                creep(),
+                energy,
+                300,
+                pos(10, 10),
+                SelectionPolicy::Nearest,
+                0,
+            )
+            .unwrap();
+        let reserved = ids
+            .iter()
+            .map(|id| {
+                let reservation = inventory.resolve_reservation(id).unwrap();
+                (reservation.target(), reservation.amount())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            reserved,
+            vec![
+                (container(11), 100),
+                (container(12), 150),
+                (container(10), 50)
+            ]
+        );
+        assert_eq!(available(&inventory, 10), 450);
+    }
+
+    #[test]
+    fn expired_reservations_can_still_be_released() {
+        let mut inventory = inventory_with(&[(10, 12, 500)]);
+        let energy = ResourceType::Energy;
+        let sink = raw_id(20);
+
+        let reservation = inventory
+            .request_from(creep(), container(10), energy, 300, 0)
+            .unwrap();
+        let delivery = inventory.reserve_delivery(creep(), sink, energy, 100, 0);
+        inventory.expire(reservation, RESERVATION_TTL + 1);
+        inventory.expire(delivery, RESERVATION_TTL + 1);
+        assert_eq!(available(&inventory, 10), 500);
+        assert_eq!(inventory.incoming_amount(sink, energy), 0);
+
+        // the tasks still hold the ids and release them once they end
+        inventory.release(reservation);
+        inventory.release(delivery);
+        assert!(inventory.expired.is_empty());
+    }
+}
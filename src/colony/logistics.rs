@@ -0,0 +1,488 @@
+//! matches haulers to resources that need moving across the whole colony
+//!
+//! every tick, the places that hold spare resources post "provide" orders and the structures that
+//! need resources post "request" orders. idle haulers are then matched to pairs of orders all at
+//! once by [`solve`], so that two haulers never go after the same resources or fill the same
+//! structure. haulers that are still carrying energy are matched straight to a request. the
+//! actual reservations are only made once a hauler takes its assignment.
+//!
+//! creeps don't post orders. workers fetch their own energy through the [`Inventory`], and
+//! energy that creeps are carrying is never offered to haulers.
+
+use std::collections::HashMap;
+
+use log::debug;
+use screeps::{
+    find, prelude::*, Creep, ObjectId, Position, RawObjectId, ResourceType, Room, StructureObject,
+};
+
+use crate::colony::{inventory::CONTROLLER_CONTAINER_RANGE, Inventory, Target};
+
+/// how full the controller container is kept, leaving room for a hauler to top it up
+const CONTROLLER_CONTAINER_FILL: f32 = 0.9;
+
+/// how urgently a request should be filled, earlier variants are filled first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RequestPriority {
+    /// spawns and extensions, nothing can be spawned without them
+    Spawning,
+    Defense,
+    Upgrading,
+    /// storage and other places that just hold resources for later
+    Storage,
+}
+
+impl RequestPriority {
+    /// how much the colony needs requests of this priority filled, from 0 to 1
+    pub fn need(self) -> f32 {
+        match self {
+            RequestPriority::Spawning => 1.0,
+            RequestPriority::Defense => 0.8,
+            RequestPriority::Upgrading => 0.5,
+            RequestPriority::Storage => 0.3,
+        }
+    }
+}
+
+/// resources that can be taken from a target
+#[derive(Debug, Clone, Copy)]
+pub struct Provide {
+    pub target: Target,
+    pub pos: Position,
+    pub kind: ResourceType,
+    /// the unreserved amount in the target
+    pub amount: u32,
+    /// whether the target only holds resources for later, like storage. buffers only provide
+    /// for requests that are more urgent than [`RequestPriority::Storage`], so resources aren't
+    /// moved from one buffer into another.
+    pub buffer: bool,
+}
+
+/// room in a structure that should be filled
+#[derive(Debug, Clone, Copy)]
+pub struct Request {
+    pub sink: RawObjectId,
+    pub pos: Position,
+    pub kind: ResourceType,
+    /// the free capacity of the sink that no hauler is bringing resources for yet
+    pub amount: u32,
+    pub priority: RequestPriority,
+}
+
+/// a creep that can be given an assignment
+#[derive(Debug, Clone, Copy)]
+pub struct Hauler {
+    pub creep: ObjectId<Creep>,
+    pub pos: Position,
+    /// how much more the creep can carry
+    pub capacity: u32,
+    /// how much energy the creep is already carrying. a loaded hauler delivers what it has
+    /// before picking anything else up.
+    pub carrying: u32,
+}
+
+/// a hauler matched to resources to pick up and a structure to deliver them to
+#[derive(Debug, Clone, Copy)]
+pub struct Assignment {
+    /// where to pick the resources up, `None` if the hauler is already carrying them
+    pub provide: Option<Provide>,
+    pub request: Request,
+    pub amount: u32,
+    /// the total distance the hauler has to travel
+    pub distance: u32,
+}
+
+/// matches haulers to pairs of orders, filling the most urgent requests first and using the
+/// hauler and provide that are closest to each request. loaded haulers are matched to requests
+/// without a provide. each hauler gets at most one assignment.
+/// ties are broken by the order of the inputs, so the result only depends on the inputs.
+pub fn solve(
+    haulers: &[Hauler],
+    provides: &[Provide],
+    requests: &[Request],
+) -> Vec<(ObjectId<Creep>, Assignment)> {
+    let mut provided = provides.iter().map(|p| p.amount).collect::<Vec<_>>();
+    let mut free = vec![true; haulers.len()];
+
+    let mut order = (0..requests.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| (requests[idx].priority, idx));
+
+    let mut assignments = Vec::new();
+    for request in order.into_iter().map(|idx| requests[idx]) {
+        let mut remaining = request.amount;
+        while remaining > 0 {
+            let best = haulers
+                .iter()
+                .enumerate()
+                .filter(|&(h, _)| free[h])
+                .flat_map(|(h, hauler)| {
+                    let delivery = (hauler.carrying > 0 && request.kind == ResourceType::Energy)
+                        .then(|| (hauler.pos.get_range_to(request.pos), h, None));
+                    let pickups = provides
+                        .iter()
+                        .enumerate()
+                        .filter(|&(p, provide)| {
+                            hauler.carrying == 0
+                                && provided[p] > 0
+                                && provide.kind == request.kind
+                                && provide.pos != request.pos
+                                && !(provide.buffer && request.priority == RequestPriority::Storage)
+                        })
+                        .map(move |(p, provide)| {
+                            let distance = hauler.pos.get_range_to(provide.pos)
+                                + provide.pos.get_range_to(request.pos);
+                            (distance, h, Some(p))
+                        });
+                    delivery.into_iter().chain(pickups)
+                })
+                .min();
+            let Some((distance, h, p)) = best else {
+                break;
+            };
+
+            let amount = match p {
+                Some(p) => {
+                    let amount = remaining.min(provided[p]).min(haulers[h].capacity);
+                    provided[p] -= amount;
+                    amount
+                }
+                None => remaining.min(haulers[h].carrying),
+            };
+            free[h] = false;
+            remaining -= amount;
+            assignments.push((
+                haulers[h].creep,
+                Assignment {
+                    provide: p.map(|p| provides[p]),
+                    request,
+                    amount,
+                    distance,
+                },
+            ));
+        }
+    }
+
+    assignments
+}
+
+/// the orders and assignments of a colony for the current tick
+#[derive(Debug, Default)]
+pub struct Logistics {
+    assignments: HashMap<ObjectId<Creep>, Assignment>,
+    /// this tick's requests, minus what haulers have already been sent to fill. kept for haulers
+    /// that need somewhere else to deliver to.
+    requests: Vec<Request>,
+}
+
+impl Logistics {
+    /// collects the orders in `rooms` and matches `haulers` to them
+    pub fn update(&mut self, rooms: &[Room], inventory: &Inventory, haulers: &[Hauler]) {
+        self.assignments.clear();
+        self.requests = collect_requests(rooms, inventory);
+        if haulers.is_empty() {
+            return;
+        }
+
+        let provides = collect_provides(rooms, inventory);
+        self.assignments = solve(haulers, &provides, &self.requests)
+            .into_iter()
+            .collect();
+        for assignment in self.assignments.values() {
+            if let Some(request) = self
+                .requests
+                .iter_mut()
+                .find(|request| request.sink == assignment.request.sink)
+            {
+                request.amount = request.amount.saturating_sub(assignment.amount);
+            }
+        }
+        debug!(
+            "{} provides, {} requests, {} assignments",
+            provides.len(),
+            self.requests.len(),
+            self.assignments.len()
+        );
+    }
+
+    /// picks a new sink for a hauler at `from` carrying `amount` of resource `kind` whose sink
+    /// `full` has no room left, taking `amount` off of the request. the most urgent request is
+    /// picked, then the closest one.
+    pub fn redirect(
+        &mut self,
+        from: Position,
+        kind: ResourceType,
+        amount: u32,
+        full: RawObjectId,
+    ) -> Option<Request> {
+        let request = self
+            .requests
+            .iter_mut()
+            .filter(|request| request.kind == kind && request.amount > 0 && request.sink != full)
+            .min_by_key(|request| (request.priority, from.get_range_to(request.pos)))?;
+        let redirected = *request;
+        request.amount = request.amount.saturating_sub(amount);
+        Some(redirected)
+    }
+
+    /// the assignment for `creep` this tick, if it got one
+    pub fn assignment(&self, creep: ObjectId<Creep>) -> Option<&Assignment> {
+        self.assignments.get(&creep)
+    }
+}
+
+/// every inventory target with spare energy
+fn collect_provides(rooms: &[Room], inventory: &Inventory) -> Vec<Provide> {
+    let controllers = rooms
+        .iter()
+        .filter_map(Room::controller)
+        .map(|controller| controller.pos())
+        .collect::<Vec<_>>();
+
+    let mut provides = inventory
+        .candidates(ResourceType::Energy)
+        .into_iter()
+        .map(|candidate| {
+            let buffer = match candidate.target {
+                Target::Storage(_) | Target::Terminal(_) | Target::Link(_) => true,
+                Target::Container(_) => controllers
+                    .iter()
+                    .any(|&pos| candidate.pos.in_range_to(pos, CONTROLLER_CONTAINER_RANGE)),
+                _ => false,
+            };
+            Provide {
+                target: candidate.target,
+                pos: candidate.pos,
+                kind: ResourceType::Energy,
+                amount: candidate.available,
+                buffer,
+            }
+        })
+        .collect::<Vec<_>>();
+    // inventory targets come out of a hash map, sort them so solving is deterministic
+    provides.sort_by_key(|provide| provide.pos.packed_repr());
+    provides
+}
+
+/// every structure in an owned room that has room for energy
+fn collect_requests(rooms: &[Room], inventory: &Inventory) -> Vec<Request> {
+    let mut requests = Vec::new();
+    for room in rooms {
+        let Some(controller) = room.controller().filter(|controller| controller.my()) else {
+            continue;
+        };
+
+        for structure in room.find(find::STRUCTURES, None) {
+            // the fraction of the structure's capacity that should be kept filled
+            let (priority, store, fill) = match &structure {
+                StructureObject::StructureSpawn(s) => (RequestPriority::Spawning, s.store(), 1.0),
+                StructureObject::StructureExtension(s) => {
+                    (RequestPriority::Spawning, s.store(), 1.0)
+                }
+                StructureObject::StructureTower(s) => (RequestPriority::Defense, s.store(), 1.0),
+                StructureObject::StructureStorage(s) if s.my() => {
+                    (RequestPriority::Storage, s.store(), 1.0)
+                }
+                StructureObject::StructureContainer(s)
+                    if s.pos()
+                        .in_range_to(controller.pos(), CONTROLLER_CONTAINER_RANGE) =>
+                {
+                    (
+                        RequestPriority::Upgrading,
+                        s.store(),
+                        CONTROLLER_CONTAINER_FILL,
+                    )
+                }
+                _ => continue,
+            };
+
+            let sink = structure.as_structure().raw_id();
+            let kind = ResourceType::Energy;
+            let wanted = (store.get_capacity(Some(kind)) as f32 * fill) as u32;
+            let amount = wanted
+                .saturating_sub(store.get_used_capacity(Some(kind)))
+                .saturating_sub(inventory.incoming_amount(sink, kind));
+            if amount > 0 {
+                requests.push(Request {
+                    sink,
+                    pos: structure.pos(),
+                    kind,
+                    amount,
+                    priority,
+                });
+            }
+        }
+    }
+    requests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_pos as pos;
+
+    fn raw_id(n: u32) -> RawObjectId {
+        format!("{:x}", n).parse().unwrap()
+    }
+
+    fn hauler(n: u32, x: u8, capacity: u32) -> Hauler {
+        Hauler {
+            creep: raw_id(n).into(),
+            pos: pos(x, 10),
+            capacity,
+            carrying: 0,
+        }
+    }
+
+    /// a hauler that is already full of energy
+    fn loaded_hauler(n: u32, x: u8, carrying: u32) -> Hauler {
+        Hauler {
+            capacity: 0,
+            carrying,
+            ..hauler(n, x, 0)
+        }
+    }
+
+    fn container(n: u32, x: u8, amount: u32) -> Provide {
+        Provide {
+            target: Target::Container(raw_id(n).into()),
+            pos: pos(x, 10),
+            kind: ResourceType::Energy,
+            amount,
+            buffer: false,
+        }
+    }
+
+    fn request(n: u32, x: u8, amount: u32, priority: RequestPriority) -> Request {
+        Request {
+            sink: raw_id(n),
+            pos: pos(x, 20),
+            kind: ResourceType::Energy,
+            amount,
+            priority,
+        }
+    }
+
+    #[test]
+    fn urgent_requests_are_filled_first() {
+        let haulers = [hauler(1, 10, 100)];
+        let provides = [container(10, 10, 500)];
+        let requests = [
+            request(20, 10, 100, RequestPriority::Storage),
+            request(21, 40, 100, RequestPriority::Spawning),
+        ];
+
+        let assignments = solve(&haulers, &provides, &requests);
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[0].1.request.sink, raw_id(21));
+    }
+
+    #[test]
+    fn closest_hauler_and_provide_are_used() {
+        let haulers = [hauler(1, 40, 100), hauler(2, 5, 100)];
+        let provides = [container(10, 45, 500), container(11, 10, 500)];
+        let requests = [request(20, 12, 50, RequestPriority::Spawning)];
+
+        let assignments = solve(&haulers, &provides, &requests);
+        assert_eq!(assignments.len(), 1);
+        let (creep, assignment) = &assignments[0];
+        assert_eq!(*creep, haulers[1].creep);
+        assert_eq!(assignment.provide.unwrap().target, provides[1].target);
+        assert_eq!(assignment.amount, 50);
+        // 5 tiles to the container and 10 from there to the request
+        assert_eq!(assignment.distance, 15);
+    }
+
+    #[test]
+    fn large_requests_are_split_between_haulers() {
+        let haulers = [hauler(1, 10, 200), hauler(2, 12, 200)];
+        let provides = [container(10, 10, 1000)];
+        let requests = [request(20, 10, 300, RequestPriority::Spawning)];
+
+        let amounts = solve(&haulers, &provides, &requests)
+            .into_iter()
+            .map(|(_, assignment)| assignment.amount)
+            .collect::<Vec<_>>();
+        assert_eq!(amounts, vec![200, 100]);
+    }
+
+    #[test]
+    fn provides_are_not_promised_twice() {
+        let haulers = [hauler(1, 10, 100), hauler(2, 12, 100)];
+        let provides = [container(10, 10, 100)];
+        let requests = [
+            request(20, 10, 100, RequestPriority::Spawning),
+            request(21, 12, 100, RequestPriority::Spawning),
+        ];
+
+        let assignments = solve(&haulers, &provides, &requests);
+        assert_eq!(assignments.len(), 1);
+        assert_eq!(assignments[0].1.request.sink, raw_id(20));
+    }
+
+    #[test]
+    fn buffers_only_provide_for_urgent_requests() {
+        let haulers = [hauler(1, 10, 100)];
+        let provides = [Provide {
+            buffer: true,
+            ..container(10, 10, 500)
+        }];
+
+        let storage = [request(20, 10, 100, RequestPriority::Storage)];
+        assert!(solve(&haulers, &provides, &storage).is_empty());
+
+        let spawning = [request(21, 10, 100, RequestPriority::Spawning)];
+        assert_eq!(solve(&haulers, &provides, &spawning).len(), 1);
+    }
+
+    #[test]
+    fn redirect_skips_the_full_sink() {
+        let mut logistics = Logistics {
+            requests: vec![
+                request(20, 11, 100, RequestPriority::Spawning),
+                request(21, 30, 100, RequestPriority::Spawning),
+                request(22, 12, 500, RequestPriority::Storage),
+            ],
+            ..Default::default()
+        };
+        let from = pos(10, 20);
+
+        let redirected = logistics.redirect(from, ResourceType::Energy, 100, raw_id(20));
+        assert_eq!(redirected.map(|request| request.sink), Some(raw_id(21)));
+
+        // the other spawning request has been filled by the first redirect
+        let redirected = logistics.redirect(from, ResourceType::Energy, 100, raw_id(20));
+        assert_eq!(redirected.map(|request| request.sink), Some(raw_id(22)));
+    }
+
+    #[test]
+    fn loaded_haulers_deliver_without_picking_up() {
+        let haulers = [hauler(1, 10, 100), loaded_hauler(2, 30, 100)];
+        let provides = [container(10, 10, 500)];
+        let requests = [request(20, 30, 150, RequestPriority::Spawning)];
+
+        let assignments = solve(&haulers, &provides, &requests);
+        assert_eq!(assignments.len(), 2);
+        let (creep, loaded) = &assignments[0];
+        assert_eq!(*creep, haulers[1].creep);
+        assert!(loaded.provide.is_none());
+        assert_eq!(loaded.amount, 100);
+        assert_eq!(loaded.distance, 10);
+        // the rest of the request is picked up by the empty hauler
+        assert_eq!(assignments[1].1.amount, 50);
+    }
+
+    #[test]
+    fn full_hauler_waits_for_an_open_sink() {
+        let haulers = [loaded_hauler(1, 10, 100)];
+        let provides = [container(10, 10, 500)];
+
+        // with every sink full the hauler is left idle, but it keeps being matched
+        assert!(solve(&haulers, &provides, &[]).is_empty());
+
+        let requests = [request(20, 30, 50, RequestPriority::Storage)];
+        let assignments = solve(&haulers, &provides, &requests);
+        assert_eq!(assignments.len(), 1);
+        assert!(assignments[0].1.provide.is_none());
+        assert_eq!(assignments[0].1.amount, 50);
+    }
+}
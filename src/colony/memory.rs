@@ -1,8 +1,14 @@
-use screeps::RoomName;
 use serde::{Deserialize, Serialize};
 
+use crate::colony::{repair::RepairConfig, room::RoomInfo, ColonyId};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ColonyMemory {
-    name: String,
-    rooms: Vec<RoomName>,
+    pub id: ColonyId,
+    pub name: String,
+    pub rooms: Vec<RoomInfo>,
+    #[serde(default)]
+    pub creeps: Vec<String>,
+    #[serde(default)]
+    pub repair: RepairConfig,
 }
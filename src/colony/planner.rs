@@ -0,0 +1,1008 @@
+//! plans the full RCL 1-8 layout of an owned room
+//!
+//! the layout is built around a compact core: a free "manager" tile in the center surrounded by
+//! the spawns, storage, terminal and other structures that need to be close together, with a ring
+//! of roads around them. everything else is placed on a checkerboard growing outwards from the
+//! core, so that every structure touches a road and all roads connect diagonally. finally the
+//! core and checkerboard are sealed off from the room exits with the fewest ramparts possible.
+//!
+//! planning is pure and only depends on a [`PlanInput`], so it can be run on terrain strings
+//! outside of the game.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    fmt,
+};
+
+use log::warn;
+use screeps::{find, prelude::*, Room, StructureType};
+use serde::{Deserialize, Serialize};
+
+use crate::util::{
+    self, ring, walking_distance, TerrainError, TerrainGrid, Tile, TileTerrain, ROOM_AREA,
+    ROOM_SIZE,
+};
+
+/// how far from the room edge structures must be, so they don't block exits
+const EDGE_MARGIN: u8 = 2;
+
+/// the structures in the ring around the manager tile, in order around the ring
+const CORE_RING: [StructureType; 8] = [
+    StructureType::Spawn,
+    StructureType::Storage,
+    StructureType::Terminal,
+    StructureType::Link,
+    StructureType::Spawn,
+    StructureType::Factory,
+    StructureType::PowerSpawn,
+    StructureType::Spawn,
+];
+
+/// the offset of each structure in [`CORE_RING`] from the center
+const CORE_OFFSETS: [(i8, i8); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+];
+
+/// the structures placed on the checkerboard outside of the core, in the order they are placed.
+/// earlier entries end up closer to the core.
+const FILL_ORDER: [(StructureType, u32); 5] = [
+    (StructureType::Tower, 6),
+    (StructureType::Extension, 60),
+    // taking these consecutively keeps them together in one cluster
+    (StructureType::Lab, 10),
+    (StructureType::Nuker, 1),
+    (StructureType::Observer, 1),
+];
+
+/// the RCL at which roads around the core are built
+const CORE_ROAD_RCL: u8 = 3;
+/// the RCL at which source containers and their roads are built
+const SOURCE_RCL: u8 = 2;
+/// the RCL at which the controller container and its road are built
+const CONTROLLER_RCL: u8 = 3;
+/// the RCL at which the mineral container and its road are built, matching the extractor
+const MINERAL_RCL: u8 = 6;
+/// the RCL at which ramparts are built, once there is enough energy to keep them repaired
+const RAMPART_RCL: u8 = 4;
+
+/// how far outside of the protected structures ramparts are placed, so that they are out of
+/// reach of most attacks from outside
+const RAMPART_MARGIN: i8 = 2;
+
+/// everything needed to plan a room
+#[derive(Debug, Clone)]
+pub struct PlanInput {
+    pub terrain: TerrainGrid,
+    pub sources: Vec<Tile>,
+    pub controller: Tile,
+    pub mineral: Option<Tile>,
+    /// structures that are already built. the core is built around an existing spawn, and
+    /// nothing else is planned on top of them. ramparts are left out, since they don't block
+    /// anything.
+    pub existing: Vec<(Tile, StructureType)>,
+}
+
+impl PlanInput {
+    /// gathers the planning input for a visible room
+    pub fn from_room(room: &Room) -> Result<Self, PlanError> {
+        let terrain = TerrainGrid::from_raw(&room.get_terrain().get_raw_buffer().to_vec())?;
+        let controller = room.controller().ok_or(PlanError::NoController)?;
+        let sources = room
+            .find(find::SOURCES, None)
+            .iter()
+            .map(|source| Tile::from(source.pos()))
+            .collect();
+        let mineral = room
+            .find(find::MINERALS, None)
+            .first()
+            .map(|mineral| Tile::from(mineral.pos()));
+        let existing = room
+            .find(find::STRUCTURES, None)
+            .iter()
+            .filter(|structure| {
+                !matches!(
+                    structure.structure_type(),
+                    StructureType::Controller | StructureType::Rampart
+                )
+            })
+            .map(|structure| (Tile::from(structure.pos()), structure.structure_type()))
+            .collect();
+
+        Ok(Self {
+            terrain,
+            sources,
+            controller: Tile::from(controller.pos()),
+            mineral,
+            existing,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedStructure {
+    pub tile: Tile,
+    pub structure_type: StructureType,
+    /// the controller level at which this structure should be built
+    pub rcl: u8,
+}
+
+/// the current version of the planner. plans made by older versions are replaced, so that
+/// changes to the layout reach rooms that have already been planned.
+pub const PLAN_VERSION: u32 = 2;
+
+/// the complete layout of a room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomPlan {
+    /// the planner version that made this plan, plans from before versioning are version 0
+    #[serde(default)]
+    pub version: u32,
+    /// the free tile in the center of the core
+    pub center: Tile,
+    pub structures: Vec<PlannedStructure>,
+}
+
+impl RoomPlan {
+    /// every structure that can be built at controller level `rcl`, in the order they should be
+    /// built
+    pub fn unlocked(&self, rcl: u8) -> impl Iterator<Item = &PlannedStructure> {
+        let mut unlocked = self
+            .structures
+            .iter()
+            .filter(|structure| structure.rcl <= rcl)
+            .collect::<Vec<_>>();
+        unlocked.sort_by_key(|structure| structure.rcl);
+        unlocked.into_iter()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanError {
+    Terrain(TerrainError),
+    NoController,
+    /// there is no open area large enough for the core
+    NoSpaceForCore,
+    /// there were not enough free tiles to place every structure
+    NotEnoughSpace {
+        structure_type: StructureType,
+        missing: u32,
+    },
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::Terrain(e) => write!(f, "{}", e),
+            PlanError::NoController => write!(f, "room has no controller"),
+            PlanError::NoSpaceForCore => write!(f, "no space for the core"),
+            PlanError::NotEnoughSpace {
+                structure_type,
+                missing,
+            } => write!(f, "no space for {} {:?}", missing, structure_type),
+        }
+    }
+}
+
+impl From<TerrainError> for PlanError {
+    fn from(e: TerrainError) -> Self {
+        PlanError::Terrain(e)
+    }
+}
+
+/// plans the full layout of a room
+pub fn plan_room(input: &PlanInput) -> Result<RoomPlan, PlanError> {
+    let mut planner = Planner::new(input);
+    let center = planner.find_center()?;
+    planner.place_core(center);
+    planner.place_resource_sites(center);
+    planner.place_fill(center)?;
+
+    let mut structures = planner.into_structures();
+    match plan_ramparts(&input.terrain, center, &structures) {
+        Some(ramparts) => structures.extend(ramparts.into_iter().map(|tile| PlannedStructure {
+            tile,
+            structure_type: StructureType::Rampart,
+            rcl: RAMPART_RCL,
+        })),
+        None => warn!("unable to seal the core around {} with ramparts", center),
+    }
+
+    Ok(RoomPlan {
+        version: PLAN_VERSION,
+        center,
+        structures,
+    })
+}
+
+/// finds the fewest rampart tiles that keep hostile creeps entering from the exits away from
+/// the core around `center` and the checkerboard structures in `structures`. structures and
+/// roads outside of the sealed area, such as source containers and links, are left unprotected.
+///
+/// returns `None` if the protected area can't be sealed off, which happens when the core or a
+/// checkerboard structure is within 2 tiles of an exit.
+pub fn plan_ramparts(
+    terrain: &TerrainGrid,
+    center: Tile,
+    structures: &[PlannedStructure],
+) -> Option<Vec<Tile>> {
+    let exits = terrain.exits();
+    // tiles this close to an exit can't be sealed, since ramparts can't be built next to exits
+    let sealable = |tile: Tile| exits.iter().all(|&exit| exit.range_to(tile) > 2);
+
+    // the core is picked by position, since links and containers elsewhere share its types
+    let core = (0..=2).flat_map(|r| ring(center, r));
+    let fill = structures
+        .iter()
+        .filter(|structure| {
+            FILL_ORDER
+                .iter()
+                .any(|(structure_type, _)| *structure_type == structure.structure_type)
+        })
+        .map(|structure| structure.tile);
+    let anchors = core
+        .chain(fill)
+        .filter(|&tile| terrain.is_walkable(tile))
+        .collect::<Vec<_>>();
+    if !anchors.iter().all(|&tile| sealable(tile)) {
+        return None;
+    }
+
+    // the margin is only a buffer, so it is narrower near exits instead of making the whole
+    // area unsealable
+    let mut protect = anchors
+        .iter()
+        .flat_map(|&tile| (0..=RAMPART_MARGIN).flat_map(move |r| ring(tile, r)))
+        .filter(|&tile| terrain.is_walkable(tile) && sealable(tile))
+        .collect::<Vec<_>>();
+    protect.sort();
+    protect.dedup();
+
+    let ramparts = util::min_cut(terrain, &protect, |_| 1)?;
+
+    // the cut only leaves the core open if min-cut itself is wrong, but a hole in the ramparts
+    // would be much worse than no plan
+    let blocked = ramparts.iter().copied().collect::<HashSet<_>>();
+    let reached = util::reachable_from_exits(terrain, &blocked);
+    if protect.iter().any(|tile| reached[tile.index()]) {
+        warn!("rampart cut around {} leaves the core reachable", center);
+        return None;
+    }
+    Some(ramparts)
+}
+
+/// whether `tile` is far enough from the edge to build on
+fn is_buildable_area(tile: Tile) -> bool {
+    (EDGE_MARGIN..ROOM_SIZE - EDGE_MARGIN).contains(&tile.x)
+        && (EDGE_MARGIN..ROOM_SIZE - EDGE_MARGIN).contains(&tile.y)
+}
+
+/// the minimum controller level at which the `index`th structure of a type can be built
+fn rcl_for(structure_type: StructureType, index: u32) -> u8 {
+    (1..=8)
+        .find(|&rcl| structure_type.controller_structures(u32::from(rcl)) > index)
+        .unwrap_or(8)
+}
+
+struct Planner<'a> {
+    input: &'a PlanInput,
+    /// what is planned on each tile
+    planned: HashMap<Tile, (StructureType, u8)>,
+    /// the order structures were planned in
+    order: Vec<Tile>,
+    /// tiles that must be kept free of structures, such as the spaces around sources
+    reserved: HashSet<Tile>,
+    /// the structure already built on each tile
+    existing: HashMap<Tile, StructureType>,
+}
+
+impl<'a> Planner<'a> {
+    fn new(input: &'a PlanInput) -> Self {
+        let mut reserved = HashSet::new();
+        let objects = input
+            .sources
+            .iter()
+            .chain(input.mineral.iter())
+            .chain(std::iter::once(&input.controller));
+        for &object in objects {
+            reserved.insert(object);
+            reserved.extend(object.neighbors());
+        }
+
+        Self {
+            input,
+            planned: HashMap::new(),
+            order: Vec::new(),
+            reserved,
+            existing: input.existing.iter().copied().collect(),
+        }
+    }
+
+    fn terrain(&self) -> &TerrainGrid {
+        &self.input.terrain
+    }
+
+    /// whether a structure other than a road can be placed on `tile`
+    fn is_free(&self, tile: Tile) -> bool {
+        is_buildable_area(tile)
+            && self.terrain().is_walkable(tile)
+            && !self.reserved.contains(&tile)
+            && !self.planned.contains_key(&tile)
+            && !self.existing.contains_key(&tile)
+    }
+
+    /// whether `structure_type` can be planned on `tile` without replacing an existing structure
+    fn fits_existing(&self, tile: Tile, structure_type: StructureType) -> bool {
+        self.existing
+            .get(&tile)
+            .is_none_or(|&existing| existing == structure_type)
+    }
+
+    fn place(&mut self, tile: Tile, structure_type: StructureType, rcl: u8) {
+        if self.planned.insert(tile, (structure_type, rcl)).is_none() {
+            self.order.push(tile);
+        }
+    }
+
+    /// plans a road, keeping the lowest RCL if a road is already planned there
+    fn place_road(&mut self, tile: Tile, rcl: u8) {
+        match self.planned.get_mut(&tile) {
+            Some((StructureType::Road, existing)) => *existing = (*existing).min(rcl),
+            Some(_) => {}
+            None => self.place(tile, StructureType::Road, rcl),
+        }
+    }
+
+    /// finds the center of the core: an open 5x5 area that is as close as possible to the
+    /// sources and controller. if the room already has a spawn, the core is built around it.
+    fn find_center(&self) -> Result<Tile, PlanError> {
+        let targets = self
+            .input
+            .sources
+            .iter()
+            .chain([self.input.controller].iter())
+            .map(|&target| walking_distance(self.terrain(), &[target]))
+            .collect::<Vec<_>>();
+        let closest = |candidates: &mut dyn Iterator<Item = Tile>| {
+            candidates
+                .filter(|&tile| self.core_fits(tile))
+                .filter_map(|tile| {
+                    let total = targets
+                        .iter()
+                        .map(|distance| distance[tile.index()])
+                        .try_fold(0_u32, |total, d| (d != u32::MAX).then_some(total + d))?;
+                    Some((total, tile))
+                })
+                .min()
+                .map(|(_, tile)| tile)
+        };
+
+        let spawns = self
+            .input
+            .existing
+            .iter()
+            .filter(|(_, structure_type)| *structure_type == StructureType::Spawn)
+            .map(|&(tile, _)| tile)
+            .collect::<Vec<_>>();
+        let mut anchored = spawns.iter().flat_map(|&spawn| {
+            CORE_OFFSETS
+                .into_iter()
+                .zip(CORE_RING)
+                .filter(|(_, structure_type)| *structure_type == StructureType::Spawn)
+                .filter_map(move |((dx, dy), _)| spawn.offset(-dx, -dy))
+        });
+        if let Some(center) = closest(&mut anchored) {
+            return Ok(center);
+        }
+        if !spawns.is_empty() {
+            warn!("the core doesn't fit around the existing spawn, planning it elsewhere");
+        }
+
+        // the core and its road ring reach 2 tiles from the center
+        closest(&mut util::open_areas(self.terrain(), 3).into_iter().flatten())
+            .ok_or(PlanError::NoSpaceForCore)
+    }
+
+    /// whether the core and its road ring fit around `center` without covering walls, reserved
+    /// tiles or existing structures of a different type
+    fn core_fits(&self, center: Tile) -> bool {
+        // the core ring must be buildable, the road ring only walkable
+        let margin = EDGE_MARGIN + 1;
+        let in_area = |c: u8| (margin..ROOM_SIZE - margin).contains(&c);
+        if !in_area(center.x) || !in_area(center.y) {
+            return false;
+        }
+
+        let core =
+            CORE_OFFSETS
+                .into_iter()
+                .zip(CORE_RING)
+                .filter_map(|((dx, dy), structure_type)| {
+                    Some((center.offset(dx, dy)?, structure_type))
+                });
+        let roads = ring(center, 2).map(|tile| (tile, StructureType::Road));
+        !self.existing.contains_key(&center)
+            && (0..=2)
+                .flat_map(|r| ring(center, r))
+                .all(|tile| self.terrain().is_walkable(tile))
+            && self
+                .reserved
+                .iter()
+                .all(|&reserved| reserved.range_to(center) > 2)
+            && core
+                .chain(roads)
+                .all(|(tile, structure_type)| self.fits_existing(tile, structure_type))
+    }
+
+    /// places the ring of core structures around `center` and the ring of roads around that
+    fn place_core(&mut self, center: Tile) {
+        let mut counts = HashMap::<StructureType, u32>::new();
+        for ((dx, dy), structure_type) in CORE_OFFSETS.into_iter().zip(CORE_RING) {
+            // find_center ensures that the whole core fits
+            let tile = center.offset(dx, dy).unwrap();
+            let count = counts.entry(structure_type).or_default();
+            self.place(tile, structure_type, rcl_for(structure_type, *count));
+            *count += 1;
+        }
+
+        for tile in ring(center, 2) {
+            self.place_road(tile, CORE_ROAD_RCL);
+        }
+    }
+
+    /// places containers and links at the sources, controller and mineral, and roads from the
+    /// core to each of them
+    fn place_resource_sites(&mut self, center: Tile) {
+        let from_center = walking_distance(self.terrain(), &[center]);
+        let closest = |planner: &Self,
+                       structure_type: StructureType,
+                       tiles: &mut dyn Iterator<Item = Tile>| {
+            tiles
+                .filter(|&tile| {
+                    is_buildable_area(tile)
+                        && planner.terrain().is_walkable(tile)
+                        && !planner.planned.contains_key(&tile)
+                        && planner.fits_existing(tile, structure_type)
+                })
+                .filter(|tile| from_center[tile.index()] != u32::MAX)
+                .min_by_key(|tile| (from_center[tile.index()], *tile))
+        };
+
+        // every link after the core link, the controller link is last so it is built last
+        let mut links = 1;
+
+        let input = self.input;
+        for &source in &input.sources {
+            let Some(container) = closest(self, StructureType::Container, &mut source.neighbors())
+            else {
+                continue;
+            };
+            self.place(container, StructureType::Container, SOURCE_RCL);
+
+            // the link must not block the container, so keep it out of the reserved area
+            if let Some(link) = closest(
+                self,
+                StructureType::Link,
+                &mut container
+                    .neighbors()
+                    .filter(|tile| !self.reserved.contains(tile)),
+            ) {
+                self.place(
+                    link,
+                    StructureType::Link,
+                    rcl_for(StructureType::Link, links),
+                );
+                links += 1;
+            }
+
+            self.place_path(center, container, SOURCE_RCL);
+        }
+
+        let controller = input.controller;
+        if let Some(container) = closest(self, StructureType::Container, &mut ring(controller, 2)) {
+            self.place(container, StructureType::Container, CONTROLLER_RCL);
+            if let Some(link) = closest(
+                self,
+                StructureType::Link,
+                &mut container
+                    .neighbors()
+                    .filter(|tile| tile.range_to(controller) > 1),
+            ) {
+                self.place(
+                    link,
+                    StructureType::Link,
+                    rcl_for(StructureType::Link, links),
+                );
+            }
+            self.place_path(center, container, CONTROLLER_RCL);
+        }
+
+        if let Some(mineral) = input.mineral {
+            self.place(mineral, StructureType::Extractor, MINERAL_RCL);
+            if let Some(container) =
+                closest(self, StructureType::Container, &mut mineral.neighbors())
+            {
+                self.place(container, StructureType::Container, MINERAL_RCL);
+                self.place_path(center, container, MINERAL_RCL);
+            }
+        }
+    }
+
+    /// plans roads along the cheapest path from the core road ring around `center` to `to`,
+    /// going around planned structures
+    fn place_path(&mut self, center: Tile, to: Tile, rcl: u8) {
+        let cost = |planner: &Self, tile: Tile| -> Option<u32> {
+            match planner.planned.get(&tile) {
+                Some((StructureType::Road, _)) => Some(1),
+                Some((StructureType::Container, _)) if tile == to => Some(1),
+                Some(_) => None,
+                None => match planner.existing.get(&tile) {
+                    Some(StructureType::Road) => Some(1),
+                    Some(StructureType::Container) if tile == to => Some(1),
+                    Some(_) => None,
+                    None => match planner.terrain().get(tile) {
+                        TileTerrain::Plain => Some(2),
+                        TileTerrain::Swamp => Some(10),
+                        TileTerrain::Wall => None,
+                    },
+                },
+            }
+        };
+
+        let mut best = vec![u32::MAX; ROOM_AREA];
+        let mut parent = vec![None::<Tile>; best.len()];
+        let mut heap = BinaryHeap::new();
+        for start in ring(center, 2) {
+            best[start.index()] = 0;
+            heap.push(Reverse((0, start)));
+        }
+
+        while let Some(Reverse((total, tile))) = heap.pop() {
+            if tile == to {
+                break;
+            }
+            if total > best[tile.index()] {
+                continue;
+            }
+            for neighbor in tile.neighbors() {
+                let Some(step) = cost(self, neighbor) else {
+                    continue;
+                };
+                let next = total + step;
+                if next < best[neighbor.index()] {
+                    best[neighbor.index()] = next;
+                    parent[neighbor.index()] = Some(tile);
+                    heap.push(Reverse((next, neighbor)));
+                }
+            }
+        }
+
+        // walk back from the destination to the ring, the container itself doesn't need a road
+        let mut tile = parent[to.index()];
+        while let Some(current) = tile {
+            self.place_road(current, rcl);
+            tile = parent[current.index()];
+        }
+    }
+
+    /// places everything in [`FILL_ORDER`] on a checkerboard around the core
+    fn place_fill(&mut self, center: Tile) -> Result<(), PlanError> {
+        let slots = self.checkerboard_slots(center);
+        let mut slots = slots.into_iter();
+
+        let mut counts = HashMap::<StructureType, u32>::new();
+        for (structure_type, amount) in FILL_ORDER {
+            for _ in 0..amount {
+                let Some((tile, road)) = slots.next() else {
+                    return Err(PlanError::NotEnoughSpace {
+                        structure_type,
+                        missing: amount - counts.get(&structure_type).copied().unwrap_or(0),
+                    });
+                };
+
+                let count = counts.entry(structure_type).or_default();
+                let rcl = rcl_for(structure_type, *count);
+                *count += 1;
+                self.place(tile, structure_type, rcl);
+                for road in road {
+                    self.place_road(road, rcl);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// finds every free tile for a structure on the checkerboard around the core, closest first,
+    /// along with the roads needed to connect it to the core
+    fn checkerboard_slots(&self, center: Tile) -> Vec<(Tile, Vec<Tile>)> {
+        let parity = (center.x + center.y) % 2;
+        let is_road_tile = |tile: Tile| (tile.x + tile.y) % 2 != parity;
+
+        // roads on the checkerboard connect diagonally, starting at the core road ring
+        let mut parent = HashMap::<Tile, Option<Tile>>::new();
+        let mut queue = VecDeque::new();
+        for tile in ring(center, 2).filter(|&tile| is_road_tile(tile)) {
+            parent.insert(tile, None);
+            queue.push_back(tile);
+        }
+
+        let mut slots = Vec::new();
+        let mut seen = HashSet::new();
+        while let Some(road) = queue.pop_front() {
+            for slot in road.orthogonal_neighbors() {
+                if !is_road_tile(slot) && self.is_free(slot) && seen.insert(slot) {
+                    let mut path = Vec::new();
+                    let mut current = Some(road);
+                    while let Some(tile) = current {
+                        path.push(tile);
+                        current = parent[&tile];
+                    }
+                    slots.push((slot, path));
+                }
+            }
+
+            for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                let Some(next) = road.offset(dx, dy) else {
+                    continue;
+                };
+                let can_be_road = is_buildable_area(next)
+                    && self.terrain().is_walkable(next)
+                    && !self.reserved.contains(&next)
+                    && self.fits_existing(next, StructureType::Road)
+                    && self
+                        .planned
+                        .get(&next)
+                        .is_none_or(|(ty, _)| *ty == StructureType::Road);
+                if can_be_road && !parent.contains_key(&next) {
+                    parent.insert(next, Some(road));
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        slots
+    }
+
+    /// the planned structures in the order they were planned. existing structures that aren't
+    /// part of the plan count towards the structure limits, so the last structures of those
+    /// types are left out and the rest are built at the RCL that makes room for them.
+    fn into_structures(self) -> Vec<PlannedStructure> {
+        let mut elsewhere = HashMap::<StructureType, u32>::new();
+        for (tile, structure_type) in &self.existing {
+            if self.planned.get(tile).map(|(ty, _)| ty) != Some(structure_type) {
+                *elsewhere.entry(*structure_type).or_default() += 1;
+            }
+        }
+
+        let mut counts = HashMap::<StructureType, u32>::new();
+        self.order
+            .into_iter()
+            .filter_map(|tile| {
+                let (structure_type, mut rcl) = self.planned[&tile];
+                if let Some(&extra) = elsewhere.get(&structure_type) {
+                    let count = counts.entry(structure_type).or_default();
+                    let index = *count + extra;
+                    *count += 1;
+                    if index >= structure_type.controller_structures(8) {
+                        return None;
+                    }
+                    rcl = rcl.max(rcl_for(structure_type, index));
+                }
+                Some(PlannedStructure {
+                    tile,
+                    structure_type,
+                    rcl,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an open room with a few walls and swamps inside of it, and exits on every side
+    const OPEN_ROOM: &str = "
+    11111111110000000000011111111111111111111111111111
+    11110000000000000000000000000000000000000000111111
+    11110000000000000000000000000000000000000000111111
+    11110000000000000000000000000000000000000000111111
+    11110000000000000000000000000000000000000000000001
+    11110000000000000000000000000000000000000000000000
+    11110000000000000000000000000000000000000000000000
+    11110000000000000000000000000000000000000000000000
+    11110000000000000000000000000000000000000000000000
+    10000000000000000000000000000000000000000000000000
+    10000000000000000000000000000000000000000000000000
+    10000000000000000000000000000000000000000000000000
+    10000000000000000000000000000000000000000000000000
+    10000000000000000000000000000000000000000000000001
+    10000111110000000000000000000000000022222220000001
+    10000111110000000000000000000000000022222220000001
+    10000111110000000000000000000000000022222220000001
+    10000111110000000000000000000000000022222220000001
+    10000111110000000000000000000000000022222220000001
+    10000111110000000000000000000000000000000000000001
+    10000111110000000000000000000000000000000000000001
+    10000000000000000000000000000000000000000000000001
+    10000000000000000000000000000000000000000000000001
+    10000000000000000000000000000000000000000000000001
+    10000000000000000000000000000000000000000000000001
+    10000000000000000000000000000000000000000000000001
+    10000000000000000000000000000000000000000000000001
+    10000000000000000000000000000000000000000000000001
+    10000000000000000000000000000011111100000000000001
+    10000000000000000000000000000011111100000000000001
+    00000000000000000011100000000011111100000000000001
+    00000000000000000011100000000011111100000000000001
+    00000000000000000011100000000011111100000000000001
+    00000000000000000011100000000011111100000000000001
+    00000000000000000011100000000000000000000000000001
+    00000000000000000011100000000000000000000000000001
+    00000000000000000011100000000000000000000000000001
+    00000000000000000000000000000000000000000000000001
+    00000000000000000000000000000000000000000000000001
+    10000000000000000000000000000000000000000000000001
+    10000000000000000000000000000000000000001111111111
+    10000000000022222222200000000000000000001111111111
+    11111110000022222222200000000000000000001111111111
+    11111110000022222222200000000000000000001111111111
+    11111110000022222222200000000000000000001111111111
+    11111110000022222222200000000000000000001111111111
+    11111110000022222222200000000000000000001111111111
+    11111110000000000000000000000000000000001111111111
+    11111110000000000000000000000000000000001111111111
+    11111111111111111111111110000000000000000111111111
+    ";
+
+    fn open_room() -> PlanInput {
+        PlanInput {
+            terrain: TerrainGrid::from_terrain_string(OPEN_ROOM).unwrap(),
+            sources: vec![Tile::new(40, 24), Tile::new(12, 38)],
+            controller: Tile::new(25, 8),
+            mineral: Some(Tile::new(44, 36)),
+            existing: Vec::new(),
+        }
+    }
+
+    /// a room with nothing but walls on its edges, and an exit in the middle of every side
+    fn empty_room() -> TerrainGrid {
+        let terrain = (0..ROOM_AREA)
+            .map(Tile::from_index)
+            .map(|tile| {
+                let exit = (20..30).contains(&tile.x) || (20..30).contains(&tile.y);
+                if tile.is_edge() && !exit {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+            .collect::<String>();
+        TerrainGrid::from_terrain_string(&terrain).unwrap()
+    }
+
+    /// whether every tile in `protected` is out of reach of creeps entering from the exits
+    fn is_sealed(
+        terrain: &TerrainGrid,
+        ramparts: &[Tile],
+        protected: impl IntoIterator<Item = Tile>,
+    ) -> bool {
+        let blocked = ramparts.iter().copied().collect::<HashSet<_>>();
+        let reached = util::reachable_from_exits(terrain, &blocked);
+        protected.into_iter().all(|tile| !reached[tile.index()])
+    }
+
+    #[test]
+    fn ramparts_surround_the_core_in_an_empty_room() {
+        let terrain = empty_room();
+        let center = Tile::new(25, 25);
+        let ramparts = plan_ramparts(&terrain, center, &[]).unwrap();
+
+        // the core reaches 2 tiles from the center, plus the margin
+        let expected = ring(center, 2 + RAMPART_MARGIN + 1).collect::<HashSet<_>>();
+        assert_eq!(ramparts.into_iter().collect::<HashSet<_>>(), expected);
+    }
+
+    #[test]
+    fn ramparts_leave_links_outside_of_the_core() {
+        let terrain = empty_room();
+        let center = Tile::new(25, 25);
+        let structures = [
+            (Tile::new(40, 40), StructureType::Link),
+            (Tile::new(41, 40), StructureType::Container),
+        ]
+        .map(|(tile, structure_type)| PlannedStructure {
+            tile,
+            structure_type,
+            rcl: 8,
+        });
+
+        assert_eq!(
+            plan_ramparts(&terrain, center, &structures),
+            plan_ramparts(&terrain, center, &[])
+        );
+    }
+
+    #[test]
+    fn ramparts_seal_checkerboard_structures() {
+        let terrain = empty_room();
+        let center = Tile::new(25, 25);
+        let extension = PlannedStructure {
+            tile: Tile::new(25, 33),
+            structure_type: StructureType::Extension,
+            rcl: 2,
+        };
+        let ramparts = plan_ramparts(&terrain, center, std::slice::from_ref(&extension)).unwrap();
+
+        let core = (0..=2).flat_map(|r| ring(center, r));
+        assert!(is_sealed(&terrain, &ramparts, core.chain([extension.tile])));
+    }
+
+    #[test]
+    fn core_next_to_an_exit_cannot_be_sealed() {
+        let terrain = empty_room();
+        assert_eq!(plan_ramparts(&terrain, Tile::new(4, 25), &[]), None);
+    }
+
+    #[test]
+    fn planned_ramparts_seal_the_core() {
+        let input = open_room();
+        let plan = plan_room(&input).unwrap();
+
+        let ramparts = plan
+            .structures
+            .iter()
+            .filter(|structure| structure.structure_type == StructureType::Rampart)
+            .map(|structure| structure.tile)
+            .collect::<Vec<_>>();
+        assert!(!ramparts.is_empty());
+
+        let fill = plan
+            .structures
+            .iter()
+            .filter(|structure| {
+                FILL_ORDER
+                    .iter()
+                    .any(|(structure_type, _)| *structure_type == structure.structure_type)
+            })
+            .map(|structure| structure.tile);
+        let core = (0..=2).flat_map(|r| ring(plan.center, r));
+        assert!(is_sealed(&input.terrain, &ramparts, core.chain(fill)));
+    }
+
+    #[test]
+    fn plan_fits_structure_limits() {
+        let plan = plan_room(&open_room()).unwrap();
+
+        for rcl in 1..=8 {
+            let mut counts = HashMap::<StructureType, u32>::new();
+            for structure in plan.unlocked(rcl) {
+                *counts.entry(structure.structure_type).or_default() += 1;
+            }
+            for (structure_type, count) in counts {
+                let limit = structure_type.controller_structures(u32::from(rcl));
+                assert!(
+                    count <= limit,
+                    "{} {:?} planned at RCL {}, only {} allowed",
+                    count,
+                    structure_type,
+                    rcl,
+                    limit
+                );
+            }
+        }
+
+        let count = |ty| {
+            plan.structures
+                .iter()
+                .filter(|structure| structure.structure_type == ty)
+                .count()
+        };
+        for (structure_type, amount) in FILL_ORDER {
+            assert_eq!(count(structure_type), amount as usize);
+        }
+        assert_eq!(count(StructureType::Spawn), 3);
+        assert_eq!(count(StructureType::Storage), 1);
+        assert_eq!(count(StructureType::Extractor), 1);
+    }
+
+    #[test]
+    fn plan_avoids_walls_and_exits() {
+        let input = open_room();
+        let plan = plan_room(&input).unwrap();
+
+        let exits = input.terrain.exits();
+        for structure in &plan.structures {
+            assert!(
+                input.terrain.is_walkable(structure.tile),
+                "{:?} planned on a wall at {}",
+                structure.structure_type,
+                structure.tile
+            );
+            assert!(
+                !structure.tile.is_edge(),
+                "{:?} planned on the edge at {}",
+                structure.structure_type,
+                structure.tile
+            );
+            if structure.structure_type != StructureType::Road {
+                assert!(
+                    exits.iter().all(|&exit| exit.range_to(structure.tile) > 1),
+                    "{:?} planned next to an exit at {}",
+                    structure.structure_type,
+                    structure.tile
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn core_is_built_around_an_existing_spawn() {
+        let spawn = Tile::new(20, 20);
+        let mut input = open_room();
+        input.existing.push((spawn, StructureType::Spawn));
+        let plan = plan_room(&input).unwrap();
+
+        assert_eq!(plan.center.range_to(spawn), 1);
+        let spawns = plan
+            .structures
+            .iter()
+            .filter(|structure| structure.structure_type == StructureType::Spawn)
+            .collect::<Vec<_>>();
+        assert_eq!(spawns.len(), 3);
+        assert!(spawns.iter().any(|structure| structure.tile == spawn));
+    }
+
+    #[test]
+    fn plan_keeps_existing_structures() {
+        let base = plan_room(&open_room()).unwrap();
+        let tower = base
+            .structures
+            .iter()
+            .find(|structure| structure.structure_type == StructureType::Tower)
+            .unwrap()
+            .tile;
+        // an extension far away from the core, where the plan has nothing
+        let extension = Tile::new(45, 5);
+        assert!(base.structures.iter().all(|s| s.tile != extension));
+
+        let mut input = open_room();
+        input.existing.push((tower, StructureType::Wall));
+        input.existing.push((extension, StructureType::Extension));
+        let plan = plan_room(&input).unwrap();
+
+        for structure in &plan.structures {
+            if let Some(&(_, existing)) = input
+                .existing
+                .iter()
+                .find(|(tile, _)| *tile == structure.tile)
+            {
+                assert_eq!(
+                    structure.structure_type, existing,
+                    "{:?} planned on top of {:?} at {}",
+                    structure.structure_type, existing, structure.tile
+                );
+            }
+        }
+
+        // the existing extension takes up one of the 60
+        let extensions = plan
+            .structures
+            .iter()
+            .filter(|structure| structure.structure_type == StructureType::Extension)
+            .collect::<Vec<_>>();
+        assert_eq!(extensions.len(), 59);
+        // extensions unlock at RCL 2, so the existing one always takes the first
+        for rcl in 2..=8 {
+            let unlocked = extensions.iter().filter(|s| s.rcl <= rcl).count() as u32;
+            assert!(unlocked < StructureType::Extension.controller_structures(u32::from(rcl)));
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! decides which structures in a colony need repairs and how urgently
+
+use std::collections::{HashMap, HashSet};
+
+use screeps::{
+    find, game, prelude::*, Creep, Position, RawObjectId, Room, StructureObject, StructureType,
+};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::colony::sources::SourceAssignments;
+
+/// how often road traffic is halved, so that old paths are forgotten
+const TRAFFIC_DECAY_INTERVAL: u32 = 500;
+
+/// tunable repair thresholds for a colony, stored in memory so they can be changed at runtime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepairConfig {
+    /// the hits that ramparts and walls are kept at, instead of their huge maximum
+    pub wall_target: u32,
+    /// structures are not repaired until they fall below this fraction of their target hits
+    pub low_watermark: f32,
+    /// once started, structures are repaired until they reach this fraction of their target hits
+    pub high_watermark: f32,
+}
+
+impl Default for RepairConfig {
+    fn default() -> Self {
+        Self {
+            wall_target: 100_000,
+            low_watermark: 0.6,
+            high_watermark: 0.95,
+        }
+    }
+}
+
+impl RepairConfig {
+    /// the hits that `structure` should be kept at
+    pub fn target_hits(&self, structure: &StructureObject) -> u32 {
+        let hits_max = structure.as_structure().hits_max();
+        match structure.structure_type() {
+            StructureType::Wall | StructureType::Rampart => hits_max.min(self.wall_target),
+            _ => hits_max,
+        }
+    }
+
+    /// the hits at which a repair of `structure` is finished
+    pub fn goal_hits(&self, structure: &StructureObject) -> u32 {
+        (self.target_hits(structure) as f32 * self.high_watermark) as u32
+    }
+}
+
+/// counts how often creeps walk over each road, to find which roads are used the most
+#[derive(Debug, Default)]
+pub struct Traffic {
+    counts: HashMap<Position, u32>,
+    max: u32,
+    /// where each creep was last tick, so creeps standing still aren't counted
+    last_positions: HashMap<String, Position>,
+}
+
+impl Traffic {
+    /// counts every creep in `creeps` that moved onto a road in `rooms` since last tick
+    pub fn record(&mut self, creeps: &[Creep], rooms: &[Room]) {
+        if game::time() % TRAFFIC_DECAY_INTERVAL == 0 {
+            self.counts.values_mut().for_each(|count| *count /= 2);
+            self.counts.retain(|_, count| *count > 0);
+            self.max /= 2;
+        }
+
+        let roads = rooms
+            .iter()
+            .flat_map(|room| room.find(find::STRUCTURES, None))
+            .filter(|structure| structure.structure_type() == StructureType::Road)
+            .map(|road| road.pos())
+            .collect::<HashSet<_>>();
+
+        let mut positions = HashMap::with_capacity(creeps.len());
+        for creep in creeps {
+            let name = creep.name();
+            let pos = creep.pos();
+            let moved = self
+                .last_positions
+                .get(&name)
+                .is_some_and(|&last| last != pos);
+            if moved && roads.contains(&pos) {
+                let count = self.counts.entry(pos).or_default();
+                *count += 1;
+                self.max = self.max.max(*count);
+            }
+            positions.insert(name, pos);
+        }
+        // creeps that died are dropped here
+        self.last_positions = positions;
+    }
+
+    /// how heavily `pos` is used relative to the busiest tile, from 0 to 1
+    pub fn relative(&self, pos: Position) -> f32 {
+        if self.max == 0 {
+            return 0.0;
+        }
+        self.counts.get(&pos).copied().unwrap_or(0) as f32 / self.max as f32
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RepairState {
+    pub config: RepairConfig,
+    pub traffic: Traffic,
+    /// structures that repairs were started on. these stay eligible for repairs until they reach
+    /// their goal hits, even once they are above the low watermark.
+    repairing: HashSet<RawObjectId>,
+}
+
+impl RepairState {
+    /// marks `structure` as being repaired, so it is repaired up to its goal hits
+    pub fn start_repair(&mut self, structure: &StructureObject) {
+        self.repairing.insert(structure.as_structure().raw_id());
+    }
+
+    /// forgets structures that reached their goal hits or no longer exist
+    pub fn update_repairing(&mut self) {
+        let config = &self.config;
+        self.repairing.retain(|id| {
+            game::get_object_by_id_erased(id)
+                .map(|object| StructureObject::from(JsValue::from(object)))
+                .is_some_and(|structure| {
+                    structure.as_structure().hits() < config.goal_hits(&structure)
+                })
+        });
+    }
+
+    /// how urgently `structure` needs repairs, higher is more urgent. returns `None` if it is
+    /// healthy enough to be left alone.
+    pub fn priority(
+        &self,
+        structure: &StructureObject,
+        sources: &SourceAssignments,
+    ) -> Option<f32> {
+        if structure.as_owned().is_some_and(|owned| !owned.my()) {
+            return None;
+        }
+
+        let target = self.config.target_hits(structure);
+        if target == 0 {
+            return None;
+        }
+        let hits = structure.as_structure().hits();
+        let ratio = hits as f32 / target as f32;
+        let repairing = self.repairing.contains(&structure.as_structure().raw_id());
+        if (ratio >= self.config.low_watermark && !repairing)
+            || hits >= self.config.goal_hits(structure)
+        {
+            return None;
+        }
+
+        let pos = structure.pos();
+        let weight = match structure.structure_type() {
+            // harvesters drop into these, letting them decay wastes all of the energy in them
+            StructureType::Container if sources.containers().any(|c| c == pos) => 4.0,
+            StructureType::Road => 1.0 + self.traffic.relative(pos),
+            StructureType::Wall | StructureType::Rampart => 0.5,
+            _ => 1.0,
+        };
+
+        Some(weight * (1.0 - ratio))
+    }
+}
@@ -1,12 +1,51 @@
+use std::rc::Rc;
+
+use log::warn;
 use screeps::RoomName;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::value::RawValue;
+
+use crate::colony::planner::RoomPlan;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoomInfo {
     name: RoomName,
     kind: RoomKind,
+    /// the planned layout of the room, only used for [`RoomKind::Owned`] rooms
+    #[serde(default)]
+    plan: Option<StoredPlan>,
+}
+
+impl RoomInfo {
+    pub fn new(name: RoomName, kind: RoomKind) -> Self {
+        Self {
+            name,
+            kind,
+            plan: None,
+        }
+    }
+
+    pub fn plan(&self) -> Option<&RoomPlan> {
+        self.plan.as_ref().map(|stored| &*stored.plan)
+    }
+
+    pub fn set_plan(&mut self, plan: RoomPlan) {
+        match StoredPlan::new(plan) {
+            Ok(stored) => self.plan = Some(stored),
+            Err(e) => warn!("unable to serialize the plan of {}: {}", self.name, e),
+        }
+    }
+
+    pub fn name(&self) -> RoomName {
+        self.name
+    }
+
+    pub fn kind(&self) -> &RoomKind {
+        &self.kind
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum RoomKind {
     /// an owned room that is meant to be the "center" of a colony, will have spawns and other
     /// infra to support other rooms in the colony
@@ -15,3 +54,36 @@ pub enum RoomKind {
     // TODO: be more granular about this, different types probably
     Remote { reserved: bool },
 }
+
+/// a room plan along with its serialized form. plans are large and rarely change, so memory is
+/// saved every tick by copying the serialized form instead of serializing the plan again.
+#[derive(Debug, Clone)]
+struct StoredPlan {
+    plan: Rc<RoomPlan>,
+    raw: Rc<RawValue>,
+}
+
+impl StoredPlan {
+    fn new(plan: RoomPlan) -> serde_json::Result<Self> {
+        let raw = serde_json::value::to_raw_value(&plan)?;
+        Ok(Self {
+            plan: Rc::new(plan),
+            raw: Rc::from(raw),
+        })
+    }
+}
+
+impl Serialize for StoredPlan {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.raw.as_ref().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for StoredPlan {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // memory is migrated as a `Value` before being deserialized, which can't be borrowed as
+        // a `RawValue`, so the plan is serialized again once when loading
+        let plan = RoomPlan::deserialize(deserializer)?;
+        Self::new(plan).map_err(de::Error::custom)
+    }
+}
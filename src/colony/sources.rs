@@ -0,0 +1,175 @@
+//! tracks which harvesters are assigned to each source in a colony, so that harvesters are spread
+//! across sources instead of all crowding the first one.
+
+use std::collections::HashMap;
+
+use log::{debug, info};
+use screeps::{
+    find, prelude::*, Creep, ObjectId, Position, Room, RoomCoordinate, Source, StructureType,
+    Terrain, ENERGY_REGEN_TIME, HARVEST_POWER,
+};
+
+use crate::util::Tile;
+
+#[derive(Debug, Default)]
+pub struct SourceAssignments {
+    sources: HashMap<ObjectId<Source>, SourceInfo>,
+}
+
+#[derive(Debug)]
+pub struct SourceInfo {
+    /// the number of WORK parts needed to drain the source in a single regen cycle
+    work_needed: u32,
+    /// every walkable tile next to the source
+    tiles: Vec<Position>,
+    /// the position of the container next to the source, if there is one. harvesters should
+    /// prefer standing on this so that their energy drops into it.
+    container: Option<Position>,
+    harvesters: HashMap<ObjectId<Creep>, Harvester>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Harvester {
+    work: u32,
+    tile: Position,
+}
+
+impl SourceInfo {
+    fn new(source: &Source, room: &Room) -> Self {
+        let terrain = room.get_terrain();
+        let pos = source.pos();
+        let tiles = Tile::from(pos)
+            .neighbors()
+            .filter(|tile| terrain.get(tile.x, tile.y) != Terrain::Wall)
+            .filter_map(|tile| {
+                let x = RoomCoordinate::new(tile.x).ok()?;
+                let y = RoomCoordinate::new(tile.y).ok()?;
+                Some(Position::new(x, y, pos.room_name()))
+            })
+            .collect();
+
+        Self {
+            work_needed: source
+                .energy_capacity()
+                .div_ceil(ENERGY_REGEN_TIME * HARVEST_POWER),
+            tiles,
+            container: None,
+            harvesters: HashMap::new(),
+        }
+    }
+
+    fn assigned_work(&self) -> u32 {
+        self.harvesters
+            .values()
+            .map(|harvester| harvester.work)
+            .sum()
+    }
+
+    /// the best tile that no harvester is standing on, preferring the container
+    fn free_tile(&self) -> Option<Position> {
+        let is_free = |tile: &Position| {
+            !self
+                .harvesters
+                .values()
+                .any(|harvester| harvester.tile == *tile)
+        };
+
+        self.container
+            .iter()
+            .chain(self.tiles.iter())
+            .find(|tile| is_free(tile))
+            .copied()
+    }
+
+    pub fn container(&self) -> Option<Position> {
+        self.container
+    }
+}
+
+impl SourceAssignments {
+    /// adds newly visible sources, refreshes their containers, and frees the slots of harvesters
+    /// that have died
+    pub fn update(&mut self, rooms: &[Room]) {
+        for room in rooms {
+            let containers = room
+                .find(find::STRUCTURES, None)
+                .into_iter()
+                .filter(|s| s.structure_type() == StructureType::Container)
+                .map(|s| s.pos())
+                .collect::<Vec<_>>();
+
+            for source in room.find(find::SOURCES, None) {
+                let info = self
+                    .sources
+                    .entry(source.id())
+                    .or_insert_with(|| SourceInfo::new(&source, room));
+                info.container = containers
+                    .iter()
+                    .find(|pos| pos.is_near_to(source.pos()))
+                    .copied();
+            }
+        }
+
+        for (source, info) in self.sources.iter_mut() {
+            info.harvesters.retain(|creep, _| {
+                let alive = creep.resolve().is_some();
+                if !alive {
+                    debug!("freeing harvester slot at {} for dead creep", source);
+                }
+                alive
+            });
+        }
+    }
+
+    /// finds the source that is most in need of harvesters and the tile a new harvester should
+    /// stand on, along with the fraction of the source's WORK that is still unassigned.
+    pub fn best_slot(&self) -> Option<(ObjectId<Source>, Position, f32)> {
+        self.sources
+            .iter()
+            .filter(|(_, info)| info.assigned_work() < info.work_needed)
+            .filter_map(|(&source, info)| {
+                let unassigned = 1.0 - info.assigned_work() as f32 / info.work_needed as f32;
+                Some((source, info.free_tile()?, unassigned))
+            })
+            .max_by(|a, b| a.2.total_cmp(&b.2))
+    }
+
+    /// assigns a harvester with `work` WORK parts to the source that is most in need of
+    /// harvesters, returning the source and the tile it should stand on.
+    pub fn assign(
+        &mut self,
+        creep: ObjectId<Creep>,
+        work: u32,
+    ) -> Option<(ObjectId<Source>, Position)> {
+        self.release(creep);
+
+        let (source, tile, _) = self.best_slot()?;
+        // best_slot only returns sources that exist
+        let info = self.sources.get_mut(&source).unwrap();
+        info.harvesters.insert(creep, Harvester { work, tile });
+        info!(
+            "assigned harvester to source {} at {} ({}/{} WORK)",
+            source,
+            tile,
+            info.assigned_work(),
+            info.work_needed
+        );
+        Some((source, tile))
+    }
+
+    /// frees any harvester slot held by `creep`
+    pub fn release(&mut self, creep: ObjectId<Creep>) {
+        for info in self.sources.values_mut() {
+            info.harvesters.remove(&creep);
+        }
+    }
+
+    pub fn get(&self, source: &ObjectId<Source>) -> Option<&SourceInfo> {
+        self.sources.get(source)
+    }
+
+    /// the positions of every container next to a source
+    pub fn containers(&self) -> impl Iterator<Item = Position> + '_ {
+        self.sources.values().filter_map(SourceInfo::container)
+    }
+}
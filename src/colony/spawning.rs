@@ -0,0 +1,335 @@
+//! decides which creeps a colony needs and spawns them
+//!
+//! every tick the colony's demand is recomputed from scratch into a [`SpawnQueue`], and idle
+//! spawns take the highest priority request that they can afford.
+
+use log::{debug, info, warn};
+use screeps::{
+    find, game, prelude::*, Creep, Part, ResourceType, StructureType, ENERGY_REGEN_TIME,
+    HARVEST_POWER, SOURCE_ENERGY_CAPACITY,
+};
+
+use crate::{
+    colony::{
+        body::{self, Travel},
+        Colony,
+    },
+    intel,
+};
+
+/// the number of workers a colony always wants, for upgrading the controller
+const BASE_WORKERS: u32 = 1;
+/// the number of extra workers a colony wants while it has construction sites
+const BUILDING_WORKERS: u32 = 1;
+
+/// the job a creep is spawned for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    /// a static creep that sits on a source and drops the energy it harvests
+    DropHarvester,
+    /// a creep that moves resources between targets
+    Hauler,
+    /// a creep that spends energy on the controller and construction
+    Worker,
+    /// a creep that only moves, exploring rooms around the colony
+    Scout,
+}
+
+impl Role {
+    /// determines the role that a creep with `body` is best suited for
+    pub fn classify(body: &[Part]) -> Option<Self> {
+        let work = body.contains(&Part::Work);
+        let carry = body.contains(&Part::Carry);
+        match (work, carry) {
+            (true, false) => Some(Role::DropHarvester),
+            (false, true) => Some(Role::Hauler),
+            (true, true) => Some(Role::Worker),
+            (false, false) if body.iter().all(|&part| part == Part::Move) => Some(Role::Scout),
+            (false, false) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// the order in which spawn requests are filled, earlier variants are spawned first
+pub enum SpawnPriority {
+    /// the colony cannot recover without this creep
+    Emergency,
+    High,
+    Normal,
+    Low,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpawnRequest {
+    priority: SpawnPriority,
+    role: Role,
+    body: Vec<Part>,
+}
+
+impl SpawnRequest {
+    pub fn priority(&self) -> SpawnPriority {
+        self.priority
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn body(&self) -> &[Part] {
+        &self.body
+    }
+
+    pub fn cost(&self) -> u32 {
+        body::cost(&self.body)
+    }
+}
+
+/// all creeps that a colony wants spawned, sorted by priority
+#[derive(Debug, Default)]
+pub struct SpawnQueue {
+    requests: Vec<SpawnRequest>,
+}
+
+impl SpawnQueue {
+    fn push(&mut self, priority: SpawnPriority, role: Role, body: Vec<Part>) {
+        if body.is_empty() {
+            debug!("not queueing {:?} with an empty body", role);
+            return;
+        }
+
+        self.requests.push(SpawnRequest {
+            priority,
+            role,
+            body,
+        });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SpawnRequest> {
+        self.requests.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+}
+
+/// counts of the creeps a colony currently has
+#[derive(Debug, Default)]
+struct Population {
+    harvesters: u32,
+    harvester_work: u32,
+    haulers: u32,
+    hauler_capacity: u32,
+    workers: u32,
+    scouts: u32,
+}
+
+impl Population {
+    fn count(creeps: &[Creep]) -> Self {
+        let mut population = Self::default();
+        for creep in creeps {
+            let body = creep
+                .body()
+                .iter()
+                .map(|part| part.part())
+                .collect::<Vec<_>>();
+            match Role::classify(&body) {
+                Some(Role::DropHarvester) => {
+                    population.harvesters += 1;
+                    population.harvester_work +=
+                        body.iter().filter(|&&part| part == Part::Work).count() as u32;
+                }
+                Some(Role::Hauler) => {
+                    population.haulers += 1;
+                    population.hauler_capacity += creep.store().get_capacity(None);
+                }
+                Some(Role::Worker) => population.workers += 1,
+                Some(Role::Scout) => population.scouts += 1,
+                None => {}
+            }
+        }
+        population
+    }
+}
+
+impl Colony {
+    /// recomputes the spawn queue from the current demand of the colony
+    pub fn update_spawn_queue(&mut self) {
+        let rooms = self.visible_rooms();
+        let Some(base_room) = game::rooms().get(self.base_room_name()) else {
+            return;
+        };
+
+        let creeps = game::creeps();
+        let creeps = self
+            .creeps
+            .iter()
+            .filter_map(|name| creeps.get(name.clone()))
+            .collect::<Vec<_>>();
+        let population = Population::count(&creeps);
+
+        let sources = rooms
+            .iter()
+            .flat_map(|room| room.find(find::SOURCES, None))
+            .collect::<Vec<_>>();
+        let spawn_pos = base_room
+            .find(find::MY_SPAWNS, None)
+            .first()
+            .map(|spawn| spawn.pos());
+        // haulers are sized for the farthest source, so they can serve any of them
+        let (haul_distance, haul_income) = sources
+            .iter()
+            .map(|source| {
+                let distance = spawn_pos.map_or(0, |pos| pos.get_range_to(source.pos()));
+                (distance, source.energy_capacity() / ENERGY_REGEN_TIME)
+            })
+            .max()
+            .unwrap_or((1, SOURCE_ENERGY_CAPACITY / ENERGY_REGEN_TIME));
+        let source_capacity = sources
+            .iter()
+            .map(|source| source.energy_capacity())
+            .max()
+            .unwrap_or(SOURCE_ENERGY_CAPACITY);
+
+        let travel = if base_room
+            .find(find::STRUCTURES, None)
+            .iter()
+            .any(|s| s.structure_type() == StructureType::Road)
+        {
+            Travel::Road
+        } else {
+            Travel::Plain
+        };
+
+        let energy_capacity = base_room.energy_capacity_available();
+        let mut queue = SpawnQueue::default();
+
+        // with no harvesters or no haulers, nothing will refill the spawns. spawn whatever can
+        // be afforded right now instead of waiting for energy that will never arrive.
+        let energy_available = base_room.energy_available();
+        if population.harvesters == 0 {
+            queue.push(
+                SpawnPriority::Emergency,
+                Role::DropHarvester,
+                body::drop_harvester(energy_available, source_capacity, Travel::Road),
+            );
+        } else if population.haulers == 0 {
+            queue.push(
+                SpawnPriority::Emergency,
+                Role::Hauler,
+                body::hauler(energy_available, haul_distance, haul_income, travel),
+            );
+        }
+
+        // enough WORK parts to drain every source once per regen cycle
+        let needed_work = sources
+            .iter()
+            .map(|source| {
+                source
+                    .energy_capacity()
+                    .div_ceil(ENERGY_REGEN_TIME * HARVEST_POWER)
+            })
+            .sum::<u32>();
+        if population.harvester_work < needed_work {
+            // harvesters only travel once, so they don't need full speed on plains
+            queue.push(
+                SpawnPriority::High,
+                Role::DropHarvester,
+                body::drop_harvester(energy_capacity, source_capacity, Travel::Road),
+            );
+        }
+
+        // haulers are needed when more energy is lying around than they can carry
+        let dropped_energy = self.inventory.loose_amount(ResourceType::Energy);
+        if population.haulers < population.harvesters.max(1)
+            || dropped_energy > population.hauler_capacity
+        {
+            queue.push(
+                SpawnPriority::Normal,
+                Role::Hauler,
+                body::hauler(energy_capacity, haul_distance, haul_income, travel),
+            );
+        }
+
+        let has_sites = rooms
+            .iter()
+            .any(|room| !room.find(find::MY_CONSTRUCTION_SITES, None).is_empty());
+        let wanted_workers = BASE_WORKERS + if has_sites { BUILDING_WORKERS } else { 0 };
+        if population.workers < wanted_workers {
+            queue.push(
+                SpawnPriority::Low,
+                Role::Worker,
+                body::worker(energy_capacity, travel),
+            );
+        }
+
+        // a single scout is enough to keep the rooms around the colony up to date
+        if population.scouts == 0 && intel::scout_target(self.base_room_name()).is_some() {
+            queue.push(SpawnPriority::Low, Role::Scout, body::scout());
+        }
+
+        queue.requests.sort_by_key(SpawnRequest::priority);
+        debug!("colony {} spawn queue: {:?}", self.name, queue.requests);
+        self.spawn_queue = queue;
+    }
+
+    /// spawns the highest priority requests on every idle spawn in the colony
+    pub fn run_spawns(&mut self) {
+        let tick = game::time();
+        for room in self.visible_rooms() {
+            if !self.owns_room(room.name()) {
+                continue;
+            }
+
+            let mut energy_available = room.energy_available();
+            for (idx, spawn) in room
+                .find(find::MY_SPAWNS, None)
+                .into_iter()
+                .filter(|spawn| spawn.spawning().is_none())
+                .enumerate()
+            {
+                let Some(request) = self.spawn_queue.requests.first() else {
+                    return;
+                };
+
+                // wait for energy instead of letting lower priority requests skip ahead
+                let cost = request.cost();
+                if cost > energy_available {
+                    debug!(
+                        "colony {} waiting for {}/{} energy to spawn {:?}",
+                        self.name,
+                        energy_available,
+                        cost,
+                        request.role()
+                    );
+                    break;
+                }
+
+                let name = format!("{} {}-{}", self.name, tick, idx);
+                match spawn.spawn_creep(request.body(), &name) {
+                    Ok(()) => {
+                        info!(
+                            "colony {} spawning {:?} {} ({:?} priority) with {} parts",
+                            self.name,
+                            request.role(),
+                            name,
+                            request.priority(),
+                            request.body().len()
+                        );
+                        energy_available -= cost;
+                        self.spawn_queue.requests.remove(0);
+                        self.creeps.insert(name);
+                    }
+                    Err(e) => {
+                        warn!("spawn {} failed to spawn {}: {:?}", spawn.name(), name, e);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn spawn_queue(&self) -> &SpawnQueue {
+        &self.spawn_queue
+    }
+}
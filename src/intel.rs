@@ -0,0 +1,197 @@
+//! what is known about rooms, including ones that aren't visible anymore
+//!
+//! visible rooms are rescanned every tick. the intel of a room is kept after it stops being
+//! visible, so decisions about rooms we can't see use what was last seen in them. scouts keep the
+//! intel of rooms around each colony fresh, and the whole store is saved in memory.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use screeps::{
+    find, game, prelude::*, Part, Position, ResourceType, Room, RoomName, StructureObject,
+};
+use serde::{Deserialize, Serialize};
+
+/// the owner of source keepers, which aren't a threat outside of source keeper rooms
+const SOURCE_KEEPER_OWNER: &str = "Source Keeper";
+
+/// how many rooms away from a colony's base room scouts go
+const SCOUT_RANGE: i32 = 2;
+
+/// how old the intel of a room has to be before it is scouted again
+const SCOUT_INTERVAL: u32 = 5_000;
+
+/// how many ticks intel is kept after a room was last seen, so memory doesn't fill up with rooms
+/// that creeps only passed through once
+const INTEL_TTL: u32 = 100_000;
+
+thread_local! {
+    static INTEL: RefCell<HashMap<RoomName, RoomIntel>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomIntel {
+    /// the name of the player that owns the controller, if anyone does
+    pub owner: Option<String>,
+    /// whether we own the controller
+    pub mine: bool,
+    pub reservation: Option<ReservationIntel>,
+    /// the level of the controller, 0 if there is no controller or nobody owns it
+    pub rcl: u8,
+    pub sources: Vec<Position>,
+    pub mineral: Option<(ResourceType, Position)>,
+    pub hostile_structures: StructureCounts,
+    /// the last tick creeps that can attack were seen in the room
+    pub hostiles_seen: Option<u32>,
+    /// the last tick the room was visible
+    pub last_seen: u32,
+    /// the last tick a scout was unable to reach the room
+    pub unreachable: Option<u32>,
+}
+
+impl RoomIntel {
+    /// whether the room is owned by another player
+    pub fn hostile_owner(&self) -> bool {
+        self.owner.is_some() && !self.mine
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReservationIntel {
+    pub username: String,
+    /// the tick the reservation runs out on
+    pub ends: u32,
+}
+
+/// the number of structures in a room that belong to other players
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StructureCounts {
+    pub spawns: u32,
+    pub towers: u32,
+    pub ramparts: u32,
+    pub other: u32,
+}
+
+/// records what is currently visible in every room, and forgets rooms that haven't been seen in
+/// a long time
+pub fn update() {
+    let now = game::time();
+    INTEL.with_borrow_mut(|intel| {
+        for room in game::rooms().values() {
+            let entry = intel.entry(room.name()).or_default();
+            scan_room(&room, entry, now);
+        }
+        intel.retain(|_, room| {
+            let updated = room.last_seen.max(room.unreachable.unwrap_or(0));
+            now.saturating_sub(updated) <= INTEL_TTL
+        });
+    });
+}
+
+fn scan_room(room: &Room, intel: &mut RoomIntel, now: u32) {
+    let controller = room.controller();
+    intel.owner = controller
+        .as_ref()
+        .and_then(|controller| controller.owner())
+        .map(|owner| owner.username());
+    intel.mine = controller
+        .as_ref()
+        .is_some_and(|controller| controller.my());
+    intel.reservation = controller
+        .as_ref()
+        .and_then(|controller| controller.reservation())
+        .map(|reservation| ReservationIntel {
+            username: reservation.username(),
+            ends: now + reservation.ticks_to_end(),
+        });
+    intel.rcl = controller
+        .filter(|controller| controller.owner().is_some())
+        .map_or(0, |controller| controller.level());
+    intel.last_seen = now;
+    intel.unreachable = None;
+
+    intel.sources = room
+        .find(find::SOURCES, None)
+        .iter()
+        .map(|source| source.pos())
+        .collect();
+    intel.mineral = room
+        .find(find::MINERALS, None)
+        .first()
+        .map(|mineral| (mineral.mineral_type(), mineral.pos()));
+
+    let mut counts = StructureCounts::default();
+    for structure in room.find(find::HOSTILE_STRUCTURES, None) {
+        match structure {
+            StructureObject::StructureSpawn(_) => counts.spawns += 1,
+            StructureObject::StructureTower(_) => counts.towers += 1,
+            StructureObject::StructureRampart(_) => counts.ramparts += 1,
+            StructureObject::StructureKeeperLair(_) => {}
+            _ => counts.other += 1,
+        }
+    }
+    intel.hostile_structures = counts;
+
+    let hostiles = room.find(find::HOSTILE_CREEPS, None).iter().any(|creep| {
+        creep.owner().username() != SOURCE_KEEPER_OWNER
+            && creep.body().iter().any(|part| {
+                part.hits() > 0 && matches!(part.part(), Part::Attack | Part::RangedAttack)
+            })
+    });
+    if hostiles {
+        intel.hostiles_seen = Some(now);
+    }
+}
+
+/// what is known about `room_name`, if it has ever been seen
+pub fn get(room_name: RoomName) -> Option<RoomIntel> {
+    INTEL.with_borrow(|intel| intel.get(&room_name).cloned())
+}
+
+/// records that a scout was unable to reach `room_name`, so it isn't tried again right away
+pub fn mark_unreachable(room_name: RoomName) {
+    let now = game::time();
+    INTEL.with_borrow_mut(|intel| {
+        intel.entry(room_name).or_default().unreachable = Some(now);
+    });
+}
+
+/// the room within [`SCOUT_RANGE`] of `center` whose intel is the most out of date, if any of them
+/// need scouting. rooms that have never been seen come first, then the closest ones.
+pub fn scout_target(center: RoomName) -> Option<RoomName> {
+    let now = game::time();
+    let stale = |seen: u32| now.saturating_sub(seen) >= SCOUT_INTERVAL;
+    INTEL.with_borrow(|intel| {
+        (-SCOUT_RANGE..=SCOUT_RANGE)
+            .flat_map(|dx| (-SCOUT_RANGE..=SCOUT_RANGE).map(move |dy| (dx, dy)))
+            .filter_map(|offset| center.checked_add(offset))
+            .filter_map(|room_name| {
+                let room = intel.get(&room_name);
+                if room.is_some_and(|room| room.unreachable.is_some_and(|tick| !stale(tick))) {
+                    return None;
+                }
+                // rooms that were only marked unreachable have never been seen
+                let last_seen = room.map(|room| room.last_seen).filter(|&seen| seen > 0);
+                if last_seen.is_some_and(|seen| !stale(seen)) {
+                    return None;
+                }
+                let distance = game::map::get_room_linear_distance(center, room_name, false);
+                Some((last_seen, distance, room_name))
+            })
+            .min_by_key(|&(last_seen, distance, room_name)| {
+                (last_seen, distance, room_name.to_string())
+            })
+            .map(|(_, _, room_name)| room_name)
+    })
+}
+
+/// replaces the intel store with intel loaded from memory
+pub fn load(rooms: HashMap<RoomName, RoomIntel>) {
+    INTEL.set(rooms);
+}
+
+/// a copy of the intel store, to be saved in memory
+pub fn snapshot() -> HashMap<RoomName, RoomIntel> {
+    INTEL.with_borrow(Clone::clone)
+}
@@ -11,19 +11,24 @@ use screeps::{game, PIXEL_CPU_COST};
 use wasm_bindgen::prelude::*;
 
 use crate::{
-    state::{GlobalState, RoomState},
+    state::{GlobalState, Memory},
     stats::{GlobalStats, TickStats},
     visualization::UiVisualizer,
 };
 
 mod colony;
+mod intel;
 mod logging;
+mod movement;
 mod state;
 mod stats;
 mod tasks;
 mod util;
 mod visualization;
 
+/// the column in each base room that the spawn queue is drawn at, to the right of the stats
+const SPAWN_QUEUE_COLUMN: f32 = 12.0;
+
 static INIT_TICK: AtomicU32 = AtomicU32::new(0);
 
 #[wasm_bindgen]
@@ -38,9 +43,12 @@ pub fn init() {
     // STATS should never be borrowed on init, so this should not panic.
     STATS.set(Some(GlobalStats::new(tick)));
 
-    // memory::MEM.with_borrow(|mem| {
-    //     tasks::init(mem);
-    // });
+    STATE.with_borrow_mut(|state| {
+        state.memory = Memory::load();
+        intel::load(std::mem::take(&mut state.memory.intel));
+        movement::load(std::mem::take(&mut state.memory.paths));
+        colony::init_colonies(state);
+    });
 }
 
 thread_local! {
@@ -64,19 +72,25 @@ pub fn game_loop() {
     }
 
     STATE.with_borrow_mut(|state| {
-        for room in game::rooms().values() {
-            let name = room.name();
-            let room_state = state
-                .room_state
-                .entry(name)
-                .or_insert_with(|| RoomState::default());
-
-            room_state.inventory.update_targets(&room);
-
-            tasks::process_tasks(state);
-
-            panic!("this is a panic");
+        intel::update();
+        colony::assign_creeps(state);
+
+        for colony in state.colonies.values_mut() {
+            colony.update_inventory();
+            colony.update_sources();
+            colony.update_construction();
+            colony.update_spawn_queue();
+            colony.run_spawns();
+            tasks::process_tasks(colony);
+
+            let mut visualizer =
+                UiVisualizer::new(Some(colony.base_room_name())).with_column(SPAWN_QUEUE_COLUMN);
+            visualizer.draw_spawn_queue(colony.name(), colony.spawn_queue());
         }
+
+        movement::resolve_traffic();
+        movement::cleanup();
+        state.save_memory();
     });
 
     process_stats(tick);
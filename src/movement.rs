@@ -0,0 +1,352 @@
+//! moves creeps along paths found with the pathfinder, instead of `Creep::move_to`
+//!
+//! paths are cached on the heap per creep and followed one step at a time, and saved in memory so
+//! they survive global resets. a creep that stops moving is assumed to be blocked and repaths
+//! around creeps. paths to other rooms only go
+//! through the rooms of a route picked by [`routing`], and are replaced if that route becomes
+//! unsafe.
+//!
+//! creeps don't move as soon as they pick a step. instead, every creep's next step is collected
+//! during the tick and [`resolve_traffic`] moves them all at once at the end of it.
+
+pub mod costs;
+pub mod routing;
+mod traffic;
+
+pub use self::traffic::MovePriority;
+
+use std::{cell::RefCell, collections::HashMap};
+
+use log::{debug, info, warn};
+use screeps::{
+    game,
+    pathfinder::{self, MultiRoomCostResult, SearchOptions},
+    prelude::*,
+    Creep, Direction, Position, RoomName,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    movement::{routing::Route, traffic::Mover},
+    util::Tile,
+};
+
+/// how many ticks a creep can fail to move before it repaths around other creeps
+const STUCK_THRESHOLD: u32 = 2;
+
+/// how many ticks a path is kept after it was last used
+const PATH_TTL: u32 = 20;
+
+/// the most operations a path search can use for each room it goes through
+const MAX_OPS: u32 = 4000;
+
+const PLAIN_COST: u8 = 2;
+const SWAMP_COST: u8 = 10;
+
+thread_local! {
+    static PATHS: RefCell<HashMap<String, CachedPath>> = RefCell::new(HashMap::new());
+}
+
+thread_local! {
+    /// what each creep wants to do this tick, cleared once traffic is resolved
+    static INTENTS: RefCell<HashMap<String, Intent>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TravelResult {
+    /// the creep is already within range of the target
+    Arrived,
+    /// the creep picked its next step, and will take it once traffic is resolved
+    Moving,
+    /// no path to the target could be found
+    NoPath,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedPath {
+    target: Position,
+    range: u32,
+    /// the rooms the path goes through
+    route: Route,
+    /// the remaining steps of the path, with the next step last
+    #[serde(with = "packed_steps")]
+    steps: Vec<Position>,
+    /// where the creep was the last time it tried to move
+    last_pos: Position,
+    /// how many ticks in a row the creep has failed to move
+    stuck: u32,
+    last_used: u32,
+}
+
+#[derive(Debug, Default)]
+struct Intent {
+    next: Option<Position>,
+    priority: MovePriority,
+}
+
+/// sets how important it is that `creep` gets where it wants to go this tick, or stays put if it
+/// doesn't move. creeps that aren't registered are idle and can be shoved around.
+pub fn register(creep: &Creep, priority: MovePriority) {
+    INTENTS.with_borrow_mut(|intents| intents.entry(creep.name()).or_default().priority = priority);
+}
+
+/// moves `creep` one step towards being within `range` of `target`
+pub fn travel_to(creep: &Creep, target: Position, range: u32) -> TravelResult {
+    let pos = creep.pos();
+    if pos.get_range_to(target) <= range {
+        PATHS.with_borrow_mut(|paths| paths.remove(&creep.name()));
+        return TravelResult::Arrived;
+    }
+
+    PATHS.with_borrow_mut(|paths| {
+        let name = creep.name();
+        let now = game::time();
+
+        let mut avoid_creeps = false;
+        if let Some(path) = paths.get_mut(&name) {
+            if path.target != target || path.range != range {
+                paths.remove(&name);
+            } else if path.route.is_unsafe(pos.room_name()) {
+                info!("route of {} to {} became unsafe, rerouting", name, target);
+                paths.remove(&name);
+            } else {
+                if path.last_pos == pos && creep.fatigue() == 0 {
+                    path.stuck += 1;
+                } else {
+                    path.stuck = 0;
+                }
+                if path.stuck >= STUCK_THRESHOLD {
+                    debug!("creep {} is stuck at {}, repathing", name, pos);
+                    avoid_creeps = true;
+                    paths.remove(&name);
+                }
+            }
+        }
+
+        if !paths.contains_key(&name) {
+            let Some(route) = Route::find(pos.room_name(), target.room_name()) else {
+                warn!("no safe route for {} from {} to {}", name, pos, target);
+                return TravelResult::NoPath;
+            };
+            let Some(steps) = find_path(pos, target, range, &route, avoid_creeps) else {
+                warn!("no path for {} from {} to {}", name, pos, target);
+                return TravelResult::NoPath;
+            };
+            paths.insert(
+                name.clone(),
+                CachedPath {
+                    target,
+                    range,
+                    route,
+                    steps,
+                    last_pos: pos,
+                    stuck: 0,
+                    last_used: now,
+                },
+            );
+        }
+
+        // the entry was either kept or just inserted
+        let path = paths.get_mut(&name).unwrap();
+        path.last_used = now;
+        path.last_pos = pos;
+        if path.steps.last() == Some(&pos) {
+            path.steps.pop();
+        }
+
+        let direction = path
+            .steps
+            .last()
+            .filter(|next| pos.is_near_to(**next))
+            .and_then(|&next| pos.get_direction_to(next));
+        let Some(direction) = direction else {
+            // the creep was pushed off of its path, find a new one next tick
+            debug!("creep {} left its path at {}", name, pos);
+            paths.remove(&name);
+            return TravelResult::Moving;
+        };
+
+        INTENTS.with_borrow_mut(|intents| {
+            intents.entry(name).or_default().next = Some(pos + direction)
+        });
+        TravelResult::Moving
+    })
+}
+
+/// moves every creep that picked a step this tick, resolving conflicts between them
+pub fn resolve_traffic() {
+    let intents = INTENTS.take();
+    let mut movers = game::creeps()
+        .values()
+        .filter(|creep| !creep.spawning())
+        .map(|creep| {
+            let name = creep.name();
+            let intent = intents.get(&name);
+            let mut next = intent.and_then(|intent| intent.next);
+            let mut priority = intent.map(|intent| intent.priority).unwrap_or_default();
+            // creeps with fatigue can neither move nor be shoved
+            if creep.fatigue() > 0 {
+                next = None;
+                priority = priority.max(MovePriority::Low);
+            }
+            Mover {
+                key: name,
+                pos: creep.pos(),
+                next,
+                priority,
+            }
+        })
+        .collect::<Vec<_>>();
+    movers.sort_by(|a, b| a.key.cmp(&b.key));
+
+    let walkable = |pos: Position| !Tile::from(pos).is_edge() && costs::is_walkable(pos);
+
+    let creeps = game::creeps();
+    for (name, direction) in traffic::resolve(&movers, walkable) {
+        let Some(creep) = creeps.get(name.clone()) else {
+            continue;
+        };
+        if let Err(e) = creep.move_direction(direction) {
+            debug!("creep {} could not move: {:?}", name, e);
+        }
+    }
+}
+
+/// replaces the cached paths with paths loaded from memory
+pub fn load(paths: HashMap<String, CachedPath>) {
+    PATHS.set(paths);
+}
+
+/// a copy of the cached paths, to be saved in memory
+pub fn snapshot() -> HashMap<String, CachedPath> {
+    PATHS.with_borrow(Clone::clone)
+}
+
+/// forgets paths that haven't been used recently, such as those of dead creeps
+pub fn cleanup() {
+    let now = game::time();
+    PATHS.with_borrow_mut(|paths| {
+        paths.retain(|_, path| now.saturating_sub(path.last_used) <= PATH_TTL);
+    });
+    costs::cleanup();
+}
+
+/// finds the steps from `from` to within `range` of `to` through the rooms of `route`, with the
+/// first step last
+fn find_path(
+    from: Position,
+    to: Position,
+    range: u32,
+    route: &Route,
+    avoid_creeps: bool,
+) -> Option<Vec<Position>> {
+    let rooms = route.rooms().len() as u32 + 1;
+    let options = SearchOptions::new(|room_name| {
+        if room_name != from.room_name() && !route.rooms().contains(&room_name) {
+            return MultiRoomCostResult::Impassable;
+        }
+        cost_matrix(room_name, avoid_creeps)
+    })
+    .plain_cost(PLAIN_COST)
+    .swamp_cost(SWAMP_COST)
+    .max_ops(MAX_OPS * rooms);
+    let result = pathfinder::search(from, to, range, Some(options));
+    if result.incomplete() {
+        return None;
+    }
+
+    let mut steps = result.path();
+    steps.reverse();
+    Some(steps)
+}
+
+/// the costs of moving through a room, from the cached cost matrices
+fn cost_matrix(room_name: RoomName, avoid_creeps: bool) -> MultiRoomCostResult {
+    MultiRoomCostResult::CostMatrix(costs::matrix(room_name, avoid_creeps).into())
+}
+
+/// stores steps in memory as the packed position of the next step followed by the direction of
+/// every step after it, which is a lot smaller than a list of positions
+mod packed_steps {
+    use screeps::Position;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{pack_steps, unpack_steps};
+
+    pub fn serialize<S: Serializer>(steps: &[Position], serializer: S) -> Result<S::Ok, S::Error> {
+        pack_steps(steps).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Position>, D::Error> {
+        // a path that can't be unpacked is left empty, so the creep finds a new one
+        let packed = Option::<(u32, String)>::deserialize(deserializer)?;
+        Ok(packed.and_then(unpack_steps).unwrap_or_default())
+    }
+}
+
+/// packs steps with the next step last into the next step and the directions from there on
+fn pack_steps(steps: &[Position]) -> Option<(u32, String)> {
+    let (&first, rest) = steps.split_last()?;
+    let mut directions = String::with_capacity(rest.len());
+    let mut pos = first;
+    for &next in rest.iter().rev() {
+        // only neighbors can be reached with a single direction
+        if !pos.is_near_to(next) {
+            return None;
+        }
+        let direction = pos.get_direction_to(next)?;
+        directions.push(char::from(b'0' + direction as u8));
+        pos = next;
+    }
+    Some((first.packed_repr(), directions))
+}
+
+/// the reverse of [`pack_steps`], or `None` if the directions are invalid
+fn unpack_steps((first, directions): (u32, String)) -> Option<Vec<Position>> {
+    let mut pos = Position::from_packed(first);
+    let mut steps = vec![pos];
+    for digit in directions.chars() {
+        let direction = digit
+            .to_digit(10)
+            .and_then(|digit| Direction::try_from(digit as u8).ok())?;
+        let next = pos + direction;
+        steps.push(next);
+        pos = next;
+    }
+    steps.reverse();
+    Some(steps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_pos as pos;
+
+    #[test]
+    fn steps_survive_packing() {
+        // with the next step last, like a cached path
+        let steps = vec![
+            pos(13, 12),
+            pos(12, 12),
+            pos(11, 11),
+            pos(10, 11),
+            pos(10, 10),
+        ];
+        let packed = pack_steps(&steps).unwrap();
+        assert_eq!(packed.0, pos(10, 10).packed_repr());
+        assert_eq!(packed.1.len(), steps.len() - 1);
+        assert_eq!(unpack_steps(packed), Some(steps));
+
+        assert_eq!(pack_steps(&[]), None);
+    }
+
+    #[test]
+    fn broken_paths_are_not_packed() {
+        assert_eq!(pack_steps(&[pos(20, 20), pos(10, 10)]), None);
+        let first = pos(10, 10).packed_repr();
+        assert_eq!(unpack_steps((first, String::from("19"))), None);
+        assert_eq!(unpack_steps((first, String::from("0"))), None);
+    }
+}
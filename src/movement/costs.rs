@@ -0,0 +1,244 @@
+//! cost matrices for rooms, cached on the heap so they don't have to be rebuilt for every search
+//!
+//! each room keeps a few layers. terrain never changes and is loaded once. structures, both ours
+//! and those of other players, are rebuilt when the structures or construction sites in the room
+//! change, or when the room is invalidated. creeps move every tick, so the creep layer is only
+//! kept for the tick it was built in. rooms that aren't visible keep the structures that were last
+//! seen in them.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use log::{debug, warn};
+use screeps::{
+    find, game, prelude::*, ConstructionSite, LocalCostMatrix, Position, Room, RoomName,
+    StructureObject, StructureType,
+};
+
+use crate::util::{TerrainGrid, Tile};
+
+/// how often visible rooms are checked for changed structures
+const CHECK_INTERVAL: u32 = 10;
+
+/// how many ticks a room is kept after it was last used
+const ROOM_TTL: u32 = 1000;
+
+const ROAD_COST: u8 = 1;
+const BLOCKED_COST: u8 = u8::MAX;
+
+thread_local! {
+    static ROOMS: RefCell<HashMap<RoomName, RoomCosts>> = RefCell::new(HashMap::new());
+}
+
+#[derive(Debug)]
+struct RoomCosts {
+    terrain: Option<TerrainGrid>,
+    structures: Option<StructureLayer>,
+    /// the tick the creep layer was built in, and the layer
+    creeps: Option<(u32, LocalCostMatrix)>,
+    /// the tick the room was invalidated in, the structure layer is rebuilt on a later tick once
+    /// the changes are visible
+    invalidated: Option<u32>,
+    last_used: u32,
+}
+
+#[derive(Debug)]
+struct StructureLayer {
+    matrix: LocalCostMatrix,
+    /// a hash of the structures and construction sites the layer was built from
+    layout: u64,
+    checked: u32,
+}
+
+impl RoomCosts {
+    fn new(room_name: RoomName) -> Self {
+        let terrain = game::map::get_room_terrain(room_name).and_then(|terrain| {
+            TerrainGrid::from_raw(&terrain.get_raw_buffer().to_vec())
+                .inspect_err(|e| warn!("unable to load terrain of {}: {}", room_name, e))
+                .ok()
+        });
+        Self {
+            terrain,
+            structures: None,
+            creeps: None,
+            invalidated: None,
+            last_used: 0,
+        }
+    }
+
+    /// rebuilds the structure layer if it is missing or the structures in the room changed
+    fn update_structures(&mut self, room_name: RoomName, now: u32) {
+        let Some(room) = game::rooms().get(room_name) else {
+            return;
+        };
+        if self.invalidated.is_some_and(|tick| tick < now) {
+            self.invalidated = None;
+            self.structures = None;
+        }
+        if let Some(layer) = &mut self.structures {
+            if now.saturating_sub(layer.checked) < CHECK_INTERVAL {
+                return;
+            }
+            layer.checked = now;
+        }
+
+        let structures = room.find(find::STRUCTURES, None);
+        let sites = room.find(find::CONSTRUCTION_SITES, None);
+        let layout = layout_hash(&structures, &sites);
+        if self
+            .structures
+            .as_ref()
+            .is_some_and(|layer| layer.layout == layout)
+        {
+            return;
+        }
+
+        debug!("rebuilding structure costs of {}", room_name);
+        let mut matrix = LocalCostMatrix::new();
+        for structure in &structures {
+            let cost = match structure {
+                StructureObject::StructureRoad(_) => ROAD_COST,
+                StructureObject::StructureContainer(_) => continue,
+                StructureObject::StructureRampart(rampart)
+                    if rampart.my() || rampart.is_public() =>
+                {
+                    continue
+                }
+                _ => BLOCKED_COST,
+            };
+            // roads under other structures are still blocked
+            if matrix.get(structure.pos().xy()) != BLOCKED_COST {
+                matrix.set(structure.pos().xy(), cost);
+            }
+        }
+
+        for site in &sites {
+            // sites of other players can be stepped on to destroy them, so only ours block
+            let walkable = !site.my()
+                || matches!(
+                    site.structure_type(),
+                    StructureType::Road | StructureType::Container | StructureType::Rampart
+                );
+            if !walkable {
+                matrix.set(site.pos().xy(), BLOCKED_COST);
+            }
+        }
+
+        self.structures = Some(StructureLayer {
+            matrix,
+            layout,
+            checked: now,
+        });
+    }
+
+    /// the creep layer for the current tick, building it if it is missing or out of date
+    fn creeps(&mut self, room: &Room, now: u32) -> &LocalCostMatrix {
+        if self.creeps.as_ref().is_none_or(|(built, _)| *built != now) {
+            let mut matrix = LocalCostMatrix::new();
+            for creep in room.find(find::CREEPS, None) {
+                matrix.set(creep.pos().xy(), BLOCKED_COST);
+            }
+            for creep in room.find(find::POWER_CREEPS, None) {
+                matrix.set(creep.pos().xy(), BLOCKED_COST);
+            }
+            self.creeps = Some((now, matrix));
+        }
+        // the layer was either kept or just built
+        &self.creeps.as_ref().unwrap().1
+    }
+}
+
+/// hashes everything the structure layer is built from, so changes are noticed even when the
+/// number of structures stays the same, like a road replacing a destroyed wall
+fn layout_hash(structures: &[StructureObject], sites: &[ConstructionSite]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for structure in structures {
+        structure.pos().packed_repr().hash(&mut hasher);
+        structure.structure_type().hash(&mut hasher);
+        if let StructureObject::StructureRampart(rampart) = structure {
+            (rampart.my() || rampart.is_public()).hash(&mut hasher);
+        }
+    }
+    for site in sites {
+        site.pos().packed_repr().hash(&mut hasher);
+        site.structure_type().hash(&mut hasher);
+        site.my().hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// runs `f` on the cached costs of `room_name`, bringing the structure layer up to date first
+fn with_room<R>(room_name: RoomName, f: impl FnOnce(&mut RoomCosts) -> R) -> R {
+    let now = game::time();
+    ROOMS.with_borrow_mut(|rooms| {
+        let costs = rooms
+            .entry(room_name)
+            .or_insert_with(|| RoomCosts::new(room_name));
+        costs.last_used = now;
+        costs.update_structures(room_name, now);
+        f(costs)
+    })
+}
+
+/// the costs of moving through `room_name` based on the structures in it, with creeps blocking
+/// their tiles if `avoid_creeps` is set. tiles left at 0 use the terrain cost.
+pub fn matrix(room_name: RoomName, avoid_creeps: bool) -> LocalCostMatrix {
+    let now = game::time();
+    with_room(room_name, |costs| {
+        let mut matrix = costs
+            .structures
+            .as_ref()
+            .map(|layer| layer.matrix.clone())
+            .unwrap_or_default();
+
+        if avoid_creeps {
+            if let Some(room) = game::rooms().get(room_name) {
+                let creeps = costs.creeps(&room, now);
+                for (xy, cost) in creeps.iter() {
+                    if cost != 0 {
+                        matrix.set(xy, cost);
+                    }
+                }
+            }
+        }
+        matrix
+    })
+}
+
+/// whether a creep could stand on `pos`, ignoring other creeps
+pub fn is_walkable(pos: Position) -> bool {
+    with_room(pos.room_name(), |costs| {
+        let tile = Tile::from(pos);
+        let terrain = costs
+            .terrain
+            .as_ref()
+            .is_some_and(|terrain| terrain.is_walkable(tile));
+        let structures = costs
+            .structures
+            .as_ref()
+            .is_none_or(|layer| layer.matrix.get(pos.xy()) != BLOCKED_COST);
+        terrain && structures
+    })
+}
+
+/// forces the structures of `room_name` to be rebuilt after this tick, for when structures or
+/// sites are known to have changed. changes made this tick only show up on the next one.
+pub fn invalidate(room_name: RoomName) {
+    let now = game::time();
+    ROOMS.with_borrow_mut(|rooms| {
+        if let Some(costs) = rooms.get_mut(&room_name) {
+            costs.invalidated = Some(now);
+        }
+    });
+}
+
+/// forgets rooms that haven't been used recently
+pub fn cleanup() {
+    let now = game::time();
+    ROOMS.with_borrow_mut(|rooms| {
+        rooms.retain(|_, costs| now.saturating_sub(costs.last_used) <= ROOM_TTL);
+    });
+}
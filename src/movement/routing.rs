@@ -0,0 +1,161 @@
+//! picks which rooms to travel through when a target is in another room
+//!
+//! rooms are scored by how dangerous they are from what [`intel`] knows about them, and routes are
+//! found with the game's route finder. a route is checked again every time it is followed, so a
+//! creep can find another way when a room ahead of it becomes more dangerous than it was.
+
+use log::debug;
+use screeps::{game, game::map::FindRouteOptions, RoomName};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::intel;
+
+/// the cost of rooms that are safe and quick to cross, like our own rooms and highways
+const SAFE_COST: f64 = 1.0;
+const DEFAULT_COST: f64 = 2.0;
+/// rooms that have never been seen might hold anything
+const UNKNOWN_COST: f64 = 3.0;
+const SOURCE_KEEPER_COST: f64 = 8.0;
+const HOSTILES_COST: f64 = 10.0;
+
+/// how many ticks a room is avoided for after hostiles were seen in it
+const HOSTILES_MEMORY: u32 = 1500;
+
+/// the kind of room, which can be told from its name alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoomType {
+    Normal,
+    Highway,
+    SourceKeeper,
+    /// the middle room of a sector, with source keeper sources but no keepers
+    Center,
+}
+
+impl RoomType {
+    fn of(room_name: RoomName) -> Self {
+        // west and north coordinates start at -1, so flip them to get the number in the name
+        let number = |coord: i32| {
+            let coord = if coord < 0 { -coord - 1 } else { coord };
+            coord % 10
+        };
+        let (x, y) = (number(room_name.x_coord()), number(room_name.y_coord()));
+        match (x, y) {
+            (0, _) | (_, 0) => RoomType::Highway,
+            (5, 5) => RoomType::Center,
+            (4..=6, 4..=6) => RoomType::SourceKeeper,
+            _ => RoomType::Normal,
+        }
+    }
+}
+
+/// how costly it is to route through `room_name`, infinite if it shouldn't be entered at all
+pub fn room_cost(room_name: RoomName) -> f64 {
+    let now = game::time();
+    let intel = intel::get(room_name);
+    if intel.as_ref().is_some_and(intel::RoomIntel::hostile_owner) {
+        return f64::INFINITY;
+    }
+    let hostiles = intel
+        .as_ref()
+        .and_then(|intel| intel.hostiles_seen)
+        .is_some_and(|seen| now.saturating_sub(seen) <= HOSTILES_MEMORY);
+    if hostiles {
+        return HOSTILES_COST;
+    }
+
+    match RoomType::of(room_name) {
+        RoomType::SourceKeeper => SOURCE_KEEPER_COST,
+        RoomType::Highway => SAFE_COST,
+        _ if intel.as_ref().is_some_and(|intel| intel.mine) => SAFE_COST,
+        _ if intel.is_none() => UNKNOWN_COST,
+        _ => DEFAULT_COST,
+    }
+}
+
+/// the rooms to travel through to get from one room to another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Route {
+    /// the rooms in order, not including the room the route starts in
+    rooms: Vec<RoomName>,
+    /// the cost of each room when the route was found. JSON has no infinity, so infinite costs
+    /// are saved as null.
+    #[serde(deserialize_with = "deserialize_costs")]
+    costs: Vec<f64>,
+}
+
+impl Route {
+    /// finds the safest route from `from` to `to`
+    pub fn find(from: RoomName, to: RoomName) -> Option<Self> {
+        if from == to {
+            return Some(Self {
+                rooms: Vec::new(),
+                costs: Vec::new(),
+            });
+        }
+
+        let options = FindRouteOptions::new().room_callback(|room_name: RoomName, _| {
+            // the target room is always allowed, the creep was sent there for a reason
+            if room_name == to {
+                SAFE_COST
+            } else {
+                room_cost(room_name)
+            }
+        });
+        match game::map::find_route(from, to, Some(options)) {
+            Ok(steps) => {
+                let rooms = steps.into_iter().map(|step| step.room).collect::<Vec<_>>();
+                let costs = rooms
+                    .iter()
+                    .map(|&room_name| room_cost(room_name))
+                    .collect();
+                Some(Self { rooms, costs })
+            }
+            Err(e) => {
+                debug!("no route from {} to {}: {:?}", from, to, e);
+                None
+            }
+        }
+    }
+
+    pub fn rooms(&self) -> &[RoomName] {
+        &self.rooms
+    }
+
+    /// whether a room that is still ahead of a creep in `current` has become more dangerous
+    /// since the route was found, so a new route should be found
+    pub fn is_unsafe(&self, current: RoomName) -> bool {
+        let ahead = self
+            .rooms
+            .iter()
+            .position(|&room_name| room_name == current)
+            .map_or(0, |idx| idx + 1);
+        // the target room is always allowed, so it is not checked either
+        let end = self.rooms.len().saturating_sub(1);
+        (ahead..end).any(|idx| room_cost(self.rooms[idx]) > self.costs[idx])
+    }
+}
+
+fn deserialize_costs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f64>, D::Error> {
+    let costs = Vec::<Option<f64>>::deserialize(deserializer)?;
+    Ok(costs
+        .into_iter()
+        .map(|cost| cost.unwrap_or(f64::INFINITY))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infinite_costs_survive_memory() {
+        let route = Route {
+            rooms: vec!["W1N1".parse().unwrap(), "W2N1".parse().unwrap()],
+            costs: vec![SAFE_COST, f64::INFINITY],
+        };
+        let json = serde_json::to_string(&route).unwrap();
+        let loaded = serde_json::from_str::<Route>(&json).unwrap();
+        assert_eq!(loaded.rooms, route.rooms);
+        assert_eq!(loaded.costs, route.costs);
+    }
+}
@@ -0,0 +1,337 @@
+//! resolves the moves of every creep in a tick together, so creeps don't block each other
+//!
+//! creeps that want to move say where they want to go, and creeps that stay put say how
+//! important it is that they aren't moved. conflicts are then resolved all at once: more important
+//! creeps get contested tiles, creeps that want each other's tiles swap, and idle creeps are
+//! shoved out of the way. resolution only depends on its inputs, not on the order creeps were
+//! added in.
+
+use std::collections::{HashMap, HashSet};
+
+use screeps::{Direction, Position};
+
+/// how important it is that a creep gets to move, or stays where it is. later variants win.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum MovePriority {
+    /// the creep has nothing to do and can be shoved anywhere
+    #[default]
+    Idle,
+    Low,
+    Normal,
+    High,
+}
+
+/// a creep taking part in traffic resolution
+#[derive(Debug, Clone)]
+pub struct Mover<K> {
+    pub key: K,
+    pub pos: Position,
+    /// the tile next to `pos` that the creep wants to move to, if any
+    pub next: Option<Position>,
+    pub priority: MovePriority,
+}
+
+/// works out which creeps can move this tick. `walkable` says whether a creep could stand on a
+/// tile, ignoring other creeps, and is used to find tiles to shove idle creeps onto.
+///
+/// returns the direction each creep that moves should move in, sorted by key.
+pub fn resolve<K: Clone + Ord>(
+    movers: &[Mover<K>],
+    walkable: impl Fn(Position) -> bool,
+) -> Vec<(K, Direction)> {
+    let mut order = (0..movers.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        movers[b]
+            .priority
+            .cmp(&movers[a].priority)
+            .then_with(|| movers[a].key.cmp(&movers[b].key))
+    });
+
+    let mut resolver = Resolver {
+        movers,
+        walkable: &walkable,
+        occupant: movers
+            .iter()
+            .enumerate()
+            .map(|(idx, mover)| (mover.pos, idx))
+            .collect(),
+        result: vec![None; movers.len()],
+        claimed: HashMap::new(),
+        visiting: HashSet::new(),
+    };
+    for idx in order {
+        if movers[idx].next.is_some() {
+            resolver.resolve(idx);
+        }
+    }
+
+    let mut moves = resolver
+        .result
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, result)| {
+            let from = movers[idx].pos;
+            let to = (*result)?;
+            (to != from).then(|| Some((movers[idx].key.clone(), from.get_direction_to(to)?)))?
+        })
+        .collect::<Vec<_>>();
+    moves.sort_by(|(a, _), (b, _)| a.cmp(b));
+    moves
+}
+
+struct Resolver<'a, K, F> {
+    movers: &'a [Mover<K>],
+    walkable: &'a F,
+    /// which creep is on each tile at the start of the tick
+    occupant: HashMap<Position, usize>,
+    /// where each creep will be at the end of the tick, once decided
+    result: Vec<Option<Position>>,
+    /// which creep will be on each tile at the end of the tick
+    claimed: HashMap<Position, usize>,
+    /// creeps whose moves are being decided further up the stack, used to detect cycles
+    visiting: HashSet<usize>,
+}
+
+impl<K, F: Fn(Position) -> bool> Resolver<'_, K, F> {
+    /// decides where creep `idx` ends up, returning whether it leaves its tile
+    fn resolve(&mut self, idx: usize) -> bool {
+        if let Some(to) = self.result[idx] {
+            return to != self.movers[idx].pos;
+        }
+
+        let pos = self.movers[idx].pos;
+        let Some(next) = self.movers[idx].next else {
+            self.stay(idx);
+            return false;
+        };
+        if self.claimed.contains_key(&next) {
+            self.stay(idx);
+            return false;
+        }
+
+        let Some(&other) = self.occupant.get(&next).filter(|&&other| other != idx) else {
+            self.go(idx, next);
+            return true;
+        };
+
+        // everyone in a cycle of creeps following each other can move at once
+        if self.visiting.contains(&other) {
+            self.go(idx, next);
+            return true;
+        }
+
+        let movers = self.movers;
+        let blocker = &movers[other];
+        if self.result[other].is_none() {
+            match blocker.next {
+                // the creeps want each other's tiles
+                Some(their_next) if their_next == pos => {
+                    self.go(idx, next);
+                    self.go(other, pos);
+                    return true;
+                }
+                Some(_) => {
+                    self.visiting.insert(idx);
+                    self.resolve(other);
+                    self.visiting.remove(&idx);
+                }
+                None if blocker.priority == MovePriority::Idle => self.shove(other, pos),
+                None => self.stay(other),
+            }
+        }
+
+        // the blocker may have claimed our tile while resolving
+        if self.result[other] != Some(next) && !self.claimed.contains_key(&next) {
+            self.go(idx, next);
+            true
+        } else {
+            self.stay(idx);
+            false
+        }
+    }
+
+    /// moves idle creep `idx` out of the way, onto a free tile next to it or onto `vacated`, the
+    /// tile of the creep that wants its tile
+    fn shove(&mut self, idx: usize, vacated: Position) {
+        let pos = self.movers[idx].pos;
+        let free = (0..8)
+            .filter_map(|i| Direction::try_from(i + 1).ok())
+            .map(|direction| pos + direction)
+            .find(|&tile| {
+                tile.room_name() == pos.room_name()
+                    && (self.walkable)(tile)
+                    && !self.occupant.contains_key(&tile)
+                    && !self.claimed.contains_key(&tile)
+            });
+
+        match free {
+            Some(tile) => self.go(idx, tile),
+            None if !self.claimed.contains_key(&vacated) => self.go(idx, vacated),
+            None => self.stay(idx),
+        }
+    }
+
+    fn go(&mut self, idx: usize, to: Position) {
+        self.result[idx] = Some(to);
+        self.claimed.insert(to, idx);
+    }
+
+    fn stay(&mut self, idx: usize) {
+        let pos = self.movers[idx].pos;
+        self.result[idx] = Some(pos);
+        self.claimed.entry(pos).or_insert(idx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_pos as pos;
+
+    fn mover(
+        key: &'static str,
+        pos: Position,
+        next: Option<Position>,
+        priority: MovePriority,
+    ) -> Mover<&'static str> {
+        Mover {
+            key,
+            pos,
+            next,
+            priority,
+        }
+    }
+
+    /// only the row at y = 10 can be walked on
+    fn corridor(tile: Position) -> bool {
+        tile.y().u8() == 10
+    }
+
+    fn open(_: Position) -> bool {
+        true
+    }
+
+    /// a deterministic shuffle, so failures can be reproduced
+    fn shuffle<T>(items: &mut [T], seed: u64) {
+        let mut state = seed;
+        for i in (1..items.len()).rev() {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            items.swap(i, (state >> 33) as usize % (i + 1));
+        }
+    }
+
+    #[test]
+    fn creeps_swap_head_on() {
+        let movers = [
+            mover("a", pos(5, 10), Some(pos(6, 10)), MovePriority::Normal),
+            mover("b", pos(6, 10), Some(pos(5, 10)), MovePriority::Low),
+        ];
+        assert_eq!(
+            resolve(&movers, corridor),
+            vec![("a", Direction::Right), ("b", Direction::Left)]
+        );
+    }
+
+    #[test]
+    fn creeps_in_a_cycle_all_move() {
+        let movers = [
+            mover("a", pos(5, 5), Some(pos(6, 5)), MovePriority::Normal),
+            mover("b", pos(6, 5), Some(pos(6, 6)), MovePriority::Normal),
+            mover("c", pos(6, 6), Some(pos(5, 5)), MovePriority::Normal),
+        ];
+        assert_eq!(
+            resolve(&movers, open),
+            vec![
+                ("a", Direction::Right),
+                ("b", Direction::Bottom),
+                ("c", Direction::TopLeft),
+            ]
+        );
+    }
+
+    #[test]
+    fn higher_priority_wins_a_contested_tile() {
+        let movers = [
+            mover("a", pos(4, 10), Some(pos(5, 10)), MovePriority::Low),
+            mover("b", pos(6, 10), Some(pos(5, 10)), MovePriority::High),
+        ];
+        assert_eq!(resolve(&movers, corridor), vec![("b", Direction::Left)]);
+
+        // ties go to the lowest key
+        let movers = [
+            mover("a", pos(4, 10), Some(pos(5, 10)), MovePriority::Normal),
+            mover("b", pos(6, 10), Some(pos(5, 10)), MovePriority::Normal),
+        ];
+        assert_eq!(resolve(&movers, corridor), vec![("a", Direction::Right)]);
+    }
+
+    #[test]
+    fn idle_creeps_are_shoved_onto_free_tiles() {
+        let movers = [
+            mover("a", pos(5, 10), Some(pos(6, 10)), MovePriority::Normal),
+            mover("b", pos(6, 10), None, MovePriority::Idle),
+        ];
+        assert_eq!(
+            resolve(&movers, corridor),
+            vec![("a", Direction::Right), ("b", Direction::Right)]
+        );
+    }
+
+    #[test]
+    fn boxed_in_idle_creeps_take_the_vacated_tile() {
+        let movers = [
+            mover("a", pos(5, 10), Some(pos(6, 10)), MovePriority::Normal),
+            mover("b", pos(6, 10), None, MovePriority::Idle),
+            mover("c", pos(7, 10), None, MovePriority::High),
+        ];
+        assert_eq!(
+            resolve(&movers, corridor),
+            vec![("a", Direction::Right), ("b", Direction::Left)]
+        );
+    }
+
+    #[test]
+    fn working_creeps_are_not_shoved() {
+        let movers = [
+            mover("a", pos(5, 10), Some(pos(6, 10)), MovePriority::Normal),
+            mover("b", pos(6, 10), None, MovePriority::Low),
+        ];
+        assert_eq!(resolve(&movers, corridor), vec![]);
+    }
+
+    #[test]
+    fn result_does_not_depend_on_input_order() {
+        let mut movers = vec![
+            // a chain whose head is contested by a more important creep
+            mover("a", pos(5, 5), Some(pos(6, 5)), MovePriority::Normal),
+            mover("b", pos(6, 5), Some(pos(7, 5)), MovePriority::Normal),
+            mover("c", pos(7, 5), Some(pos(8, 5)), MovePriority::Normal),
+            mover("d", pos(8, 6), Some(pos(8, 5)), MovePriority::High),
+            // an idle creep in the way of another one
+            mover("e", pos(10, 10), None, MovePriority::Idle),
+            mover("f", pos(9, 9), Some(pos(10, 10)), MovePriority::Low),
+            // two creeps after the tile of a creep that is leaving it
+            mover("g", pos(20, 20), Some(pos(21, 21)), MovePriority::Normal),
+            mover("h", pos(22, 20), Some(pos(21, 21)), MovePriority::Normal),
+            mover("i", pos(21, 21), Some(pos(21, 22)), MovePriority::Low),
+        ];
+        let expected = resolve(&movers, open);
+        assert_eq!(
+            expected,
+            vec![
+                ("d", Direction::Top),
+                ("e", Direction::Top),
+                ("f", Direction::BottomRight),
+                ("g", Direction::BottomRight),
+                ("i", Direction::Bottom),
+            ]
+        );
+
+        for seed in 0..20 {
+            shuffle(&mut movers, seed);
+            assert_eq!(resolve(&movers, open), expected, "seed {}", seed);
+        }
+    }
+}
@@ -1,30 +1,197 @@
-use core::sync::atomic::AtomicU32;
 use std::collections::HashMap;
 
-use screeps::RoomName;
+use js_sys::JsString;
+use log::{info, warn};
+use screeps::{raw_memory, ResourceType, RoomName};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::{colony::Inventory, tasks::RoomTasks};
+use crate::{
+    colony::{memory::ColonyMemory, Colony, ColonyId, ReservationId},
+    intel::{self, RoomIntel},
+    movement::{self, CachedPath},
+};
 
 #[derive(Debug, Default)]
 pub struct GlobalState {
-    pub room_state: HashMap<RoomName, RoomState>,
+    pub colonies: HashMap<ColonyId, Colony>,
     pub memory: Memory,
 }
 
-#[derive(Debug, Default)]
-pub struct RoomState {
-    pub inventory: Inventory,
-    pub tasks: RoomTasks,
+impl GlobalState {
+    /// copies the persistent parts of the state into memory and writes it to `RawMemory`
+    pub fn save_memory(&mut self) {
+        self.memory.colonies = self.colonies.values().map(Colony::to_memory).collect();
+        self.memory.intel = intel::snapshot();
+        self.memory.paths = movement::snapshot();
+        self.memory.save();
+    }
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+/// the current version of the [`Memory`] schema. this must be incremented whenever a change is
+/// made to `Memory` that old blobs cannot be deserialized into, and a migration must be added to
+/// [`MIGRATIONS`] to upgrade the previous version.
+const MEMORY_VERSION: u32 = 1;
+
+/// migrations to upgrade memory from one version to the next. the migration at index `n` upgrades
+/// memory from version `n` to version `n + 1`.
+const MIGRATIONS: [fn(&mut Value); MEMORY_VERSION as usize] = [migrate_v0];
+
+/// persistent memory, stored in `RawMemory` as JSON.
+///
+/// fields that are missing from a stored blob are filled in from the default, so adding a field
+/// does not require a migration.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Memory {
+    /// the schema version this memory was written with
+    pub version: u32,
     pub next_colony_id: u32,
+    /// only populated while loading and saving, the live colonies are in [`GlobalState`]
+    pub colonies: Vec<ColonyMemory>,
+    /// only populated while loading and saving, the live intel is kept by [`intel`]
+    pub intel: HashMap<RoomName, RoomIntel>,
+    /// only populated while loading and saving, the live paths are kept by [`movement`]
+    pub paths: HashMap<String, CachedPath>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self {
+            version: MEMORY_VERSION,
+            next_colony_id: 0,
+            colonies: Vec::new(),
+            intel: HashMap::new(),
+            paths: HashMap::new(),
+        }
+    }
+}
+
+impl Memory {
+    /// loads memory from `RawMemory`, migrating it to the current version if needed.
+    ///
+    /// if memory is empty, corrupt, or from a newer version, this logs the problem and returns
+    /// the default memory instead.
+    pub fn load() -> Self {
+        let raw = String::from(raw_memory::get());
+        if raw.is_empty() {
+            info!("RawMemory was empty, using default memory");
+            return Self::default();
+        }
+
+        match Self::deserialize(&raw) {
+            Ok(memory) => memory,
+            Err(e) => {
+                warn!("unable to load memory, replacing with default: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// writes memory back to `RawMemory`
+    pub fn save(&self) {
+        match serde_json::to_string(self) {
+            Ok(raw) => raw_memory::set(&JsString::from(raw)),
+            Err(e) => warn!("unable to serialize memory: {}", e),
+        }
+    }
+
+    fn deserialize(raw: &str) -> Result<Self, String> {
+        let mut value = serde_json::from_str::<Value>(raw).map_err(|e| e.to_string())?;
+        let Some(object) = value.as_object() else {
+            return Err(String::from("memory was not an object"));
+        };
+
+        // memory from before versioning existed has no version field
+        let version = match object.get("version") {
+            Some(version) => version
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| format!("invalid memory version {}", version))?,
+            None => 0,
+        };
+
+        if version > MEMORY_VERSION {
+            return Err(format!(
+                "memory version {} is newer than the current version {}",
+                version, MEMORY_VERSION
+            ));
+        }
+
+        for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            info!("migrating memory from version {} to {}", from, from + 1);
+            migration(&mut value);
+        }
+
+        serde_json::from_value(value).map_err(|e| e.to_string())
+    }
+}
+
+/// version 0 is unversioned memory, it only needs the version field added.
+fn migrate_v0(value: &mut Value) {
+    if let Some(object) = value.as_object_mut() {
+        object.insert(String::from("version"), Value::from(1));
+    }
 }
 
 #[derive(Debug)]
 pub enum HaulState {
-    Gathering,
-    Delivering,
+    /// collecting resources of this type from each reservation in order
+    Gathering(ResourceType, Vec<ReservationId>),
+    /// carrying resources of this type to the target, the reservations have been released
+    Delivering(ResourceType),
+}
+
+/// the state of a task that gathers energy and then spends it on something
+#[derive(Debug)]
+pub enum WorkState {
+    /// collecting the energy held by the reservation
+    Gathering(ReservationId),
+    /// spending energy until the creep is empty
+    Working,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unversioned_memory_is_migrated() {
+        let memory = Memory::deserialize(r#"{"next_colony_id": 3, "colonies": []}"#).unwrap();
+        assert_eq!(memory.version, MEMORY_VERSION);
+        assert_eq!(memory.next_colony_id, 3);
+    }
+
+    #[test]
+    fn current_memory_is_loaded() {
+        let raw = format!(
+            r#"{{"version": {}, "next_colony_id": 5, "colonies": [], "intel": {{}}}}"#,
+            MEMORY_VERSION
+        );
+        let memory = Memory::deserialize(&raw).unwrap();
+        assert_eq!(memory.version, MEMORY_VERSION);
+        assert_eq!(memory.next_colony_id, 5);
+    }
+
+    #[test]
+    fn newer_memory_is_rejected() {
+        let raw = format!(r#"{{"version": {}}}"#, MEMORY_VERSION + 1);
+        assert!(Memory::deserialize(&raw).is_err());
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let raw = format!(r#"{{"version": {}}}"#, MEMORY_VERSION);
+        let memory = Memory::deserialize(&raw).unwrap();
+        assert_eq!(memory.next_colony_id, 0);
+        assert!(memory.colonies.is_empty());
+        assert!(memory.intel.is_empty());
+    }
+
+    #[test]
+    fn invalid_memory_is_rejected() {
+        assert!(Memory::deserialize("[]").is_err());
+        assert!(Memory::deserialize(r#"{"version": "one"}"#).is_err());
+        assert!(Memory::deserialize("{").is_err());
+    }
 }
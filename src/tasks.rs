@@ -2,36 +2,119 @@ use std::collections::HashMap;
 
 use log::{debug, trace, warn};
 use screeps::{
-    find, game, Creep, HasId, MaybeHasId, ObjectId, Part, RawObjectId, Room, SharedCreepProperties,
-    Source,
+    game, prelude::*, ConstructionSite, Creep, ObjectId, Position, RawObjectId, ResourceType, Room,
+    RoomName, Source, StructureController,
 };
 
 use crate::{
-    colony::{Inventory, ReservationId},
-    state::HaulState,
-    GlobalState,
+    colony::{
+        logistics::{Hauler, Logistics},
+        sources::SourceAssignments,
+        spawning::Role,
+        Colony, Inventory, ReservationId,
+    },
+    movement::{self, MovePriority},
+    state::{HaulState, WorkState},
+    tasks::{
+        build::BuildScorer,
+        drop_harvest::DropHarvestScorer,
+        haul::HaulScorer,
+        repair::RepairScorer,
+        scoring::{BodyComposition, TaskContext, TaskScorer},
+        scout::ScoutScorer,
+        upgrade::UpgradeScorer,
+    },
 };
 
+mod build;
 mod drop_harvest;
+mod gather;
 mod haul;
+mod repair;
+mod scoring;
+mod scout;
+mod upgrade;
 
 #[derive(Debug)]
 pub enum Task {
-    DropHarvest(ObjectId<Source>),
-    /// a task to haul a specified resource to a target store
+    /// a task to harvest a source while standing on a specific tile next to it, letting the
+    /// energy drop
+    DropHarvest(ObjectId<Source>, Position),
+    /// a task to haul a specified resource to a target store, holding a delivery reservation for
+    /// room in the store
     /// INVARIANT: the target store must always be able to store the resource type for the
-    /// reservation. it may not have room for the reservation, but code will handle that on a
-    /// case-by-case basis.
+    /// reservation. it may not have room for the reservation, in which case the creep is sent
+    /// on to another store with the rest.
     Haul(HaulState, ReservationId, RawObjectId),
+    /// a task to gather energy and spend it upgrading a controller
+    Upgrade(WorkState, ObjectId<StructureController>),
+    /// a task to gather energy and spend it building a construction site
+    Build(WorkState, ObjectId<ConstructionSite>),
+    /// a task to gather energy and spend it repairing a structure until it reaches the goal hits
+    Repair(WorkState, RawObjectId, u32),
+    /// a task to travel to a room so that its intel is updated
+    Scout(RoomName),
 }
 
 impl Task {
-    fn execute(&mut self, inventory: &mut Inventory, creep: &Creep) -> TaskResult {
+    fn execute(
+        &mut self,
+        inventory: &mut Inventory,
+        logistics: &mut Logistics,
+        creep: &Creep,
+    ) -> TaskResult {
         match self {
-            Task::DropHarvest(source_id) => drop_harvest::run(source_id, creep),
-            Task::Haul(haul_state, reservation_id, target) => {
-                haul::run(haul_state, inventory, creep, reservation_id, target)
+            Task::DropHarvest(source_id, tile) => drop_harvest::run(source_id, tile, creep),
+            Task::Haul(haul_state, delivery, target) => {
+                haul::run(haul_state, inventory, logistics, creep, delivery, target)
             }
+            Task::Upgrade(work_state, controller) => {
+                upgrade::run(work_state, inventory, creep, controller)
+            }
+            Task::Build(work_state, site) => build::run(work_state, inventory, creep, site),
+            Task::Repair(work_state, target, goal) => {
+                repair::run(work_state, inventory, creep, target, *goal)
+            }
+            Task::Scout(room_name) => scout::run(room_name, creep),
+        }
+    }
+
+    /// how important it is that a creep doing this task gets through traffic
+    fn move_priority(&self) -> MovePriority {
+        match self {
+            Task::DropHarvest(..) => MovePriority::High,
+            Task::Haul(..) => MovePriority::Normal,
+            Task::Upgrade(..) | Task::Build(..) | Task::Repair(..) | Task::Scout(..) => {
+                MovePriority::Low
+            }
+        }
+    }
+
+    /// releases everything held by this task so it can be dropped before it finishes
+    fn abandon(
+        self,
+        id: ObjectId<Creep>,
+        inventory: &mut Inventory,
+        sources: &mut SourceAssignments,
+    ) {
+        match self {
+            Task::DropHarvest(..) => sources.release(id),
+            Task::Haul(HaulState::Gathering(_, reservations), delivery, _) => {
+                for reservation_id in reservations {
+                    inventory.release(reservation_id);
+                }
+                inventory.release(delivery);
+            }
+            Task::Haul(HaulState::Delivering(_), delivery, _) => inventory.release(delivery),
+            Task::Upgrade(WorkState::Gathering(reservation_id), _)
+            | Task::Build(WorkState::Gathering(reservation_id), _)
+            | Task::Repair(WorkState::Gathering(reservation_id), ..) => {
+                inventory.release(reservation_id)
+            }
+            Task::Upgrade(WorkState::Working, _)
+            | Task::Build(WorkState::Working, _)
+            | Task::Repair(WorkState::Working, ..)
+            | Task::Scout(..) => {}
         }
     }
 }
@@ -46,42 +129,121 @@ pub enum TaskResult {
     Error,
 }
 
+/// the tasks of every creep in a colony
 #[derive(Debug, Default)]
-pub struct RoomTasks {
+pub struct ColonyTasks {
     tasks: HashMap<ObjectId<Creep>, Task>,
 }
 
-pub fn process_tasks(state: &mut GlobalState) {
-    for creep in game::creeps().values() {
+impl ColonyTasks {
+    /// drops the tasks of creeps that no longer exist, releasing everything they held
+    pub fn remove_dead(&mut self, inventory: &mut Inventory, sources: &mut SourceAssignments) {
+        let dead = self
+            .tasks
+            .keys()
+            .filter(|id| id.resolve().is_none())
+            .copied()
+            .collect::<Vec<_>>();
+
+        for id in dead {
+            if let Some(task) = self.tasks.remove(&id) {
+                debug!("dropping task {:?} of dead creep", task);
+                task.abandon(id, inventory, sources);
+            }
+        }
+    }
+}
+
+pub fn process_tasks(colony: &mut Colony) {
+    colony
+        .tasks
+        .remove_dead(&mut colony.inventory, &mut colony.sources);
+    colony.inventory.sweep_reservations(game::time());
+    colony.repair.update_repairing();
+
+    let Some(base_room) = game::rooms().get(colony.base_room_name()) else {
+        warn!(
+            "base room {} of colony {} is not visible",
+            colony.base_room_name(),
+            colony.name()
+        );
+        return;
+    };
+
+    protect_controller(colony, &base_room);
+
+    let rooms = colony.visible_rooms();
+    let creeps = game::creeps();
+    let creeps = colony
+        .creeps()
+        .iter()
+        .filter_map(|name| creeps.get(name.clone()))
+        .collect::<Vec<_>>();
+    colony.repair.traffic.record(&creeps, &rooms);
+
+    // idle haulers are matched to haul orders all at once, before any of them picks a task.
+    // workers gather their own energy, so they don't take orders meant for haulers.
+    let haulers = creeps
+        .iter()
+        .filter(|creep| !creep.spawning())
+        .filter(|creep| {
+            let body = creep
+                .body()
+                .iter()
+                .map(|part| part.part())
+                .collect::<Vec<_>>();
+            Role::classify(&body) == Some(Role::Hauler)
+        })
+        .filter_map(|creep| {
+            let id = creep.try_id()?;
+            let store = creep.store();
+            let capacity = store.get_free_capacity(Some(ResourceType::Energy)).max(0) as u32;
+            let carrying = store.get_used_capacity(Some(ResourceType::Energy));
+            let idle = !colony.tasks.tasks.contains_key(&id);
+            (idle && (capacity > 0 || carrying > 0)).then(|| Hauler {
+                creep: id,
+                pos: creep.pos(),
+                capacity,
+                carrying,
+            })
+        })
+        .collect::<Vec<_>>();
+    colony.logistics.update(&rooms, &colony.inventory, &haulers);
+
+    for creep in creeps {
         debug!("processing creep {}", creep.name());
         if creep.spawning() {
             trace!("skipping spawning creep");
             continue;
         }
 
-        // creeps always have a room
-        let room = creep.room().unwrap();
-        let room_name = room.name();
-        let Some(room_state) = state.room_state.get_mut(&room_name) else {
-            warn!("no RoomState for {}", room_name);
-            continue;
-        };
-
-        let inventory = &mut room_state.inventory;
-        let tasks = &mut room_state.tasks;
+        let inventory = &mut colony.inventory;
+        let tasks = &mut colony.tasks;
+        let sources = &mut colony.sources;
 
         let id = creep
             .try_id()
             .expect("creeps that have been spawned should have an id");
         if tasks.tasks.contains_key(&id) {
-            execute_task_common(tasks, id, inventory);
+            execute_task_common(tasks, id, inventory, &mut colony.logistics, sources);
         } else {
             debug!("reassigning task for {}", creep.name());
             // reassign task based on what creep would be most suited for
-            if let Some(task) = find_best_task(&creep, &room, inventory) {
+            let mut ctx = TaskContext {
+                creep: &creep,
+                id,
+                body: BodyComposition::new(&creep),
+                base_room: &base_room,
+                rooms: &rooms,
+                inventory,
+                sources,
+                repair: &mut colony.repair,
+                logistics: &colony.logistics,
+            };
+            if let Some(task) = find_best_task(&mut ctx) {
                 debug!("creep {}: {:?}", creep.name(), task);
                 tasks.tasks.insert(id, task);
-                execute_task_common(tasks, id, inventory);
+                execute_task_common(tasks, id, inventory, &mut colony.logistics, sources);
             } else {
                 debug!("creep {} not assigned a task", creep.name());
             }
@@ -89,42 +251,115 @@ pub fn process_tasks(state: &mut GlobalState) {
     }
 }
 
+/// if the base room's controller is about to downgrade and nobody is upgrading it, pulls the
+/// closest creep that can upgrade off of its current task to upgrade instead
+fn protect_controller(colony: &mut Colony, base_room: &Room) {
+    let Some(controller) = base_room.controller() else {
+        return;
+    };
+    if !upgrade::needs_protection(&controller)
+        || colony
+            .tasks
+            .tasks
+            .values()
+            .any(|task| matches!(task, Task::Upgrade(..)))
+    {
+        return;
+    }
+
+    let creeps = game::creeps();
+    let Some((creep, id)) = colony
+        .creeps()
+        .iter()
+        .filter_map(|name| creeps.get(name.clone()))
+        .filter(|creep| !creep.spawning())
+        .filter(|creep| {
+            let body = BodyComposition::new(creep);
+            body.work > 0 && body.carry > 0
+        })
+        .filter_map(|creep| {
+            let id = creep.try_id()?;
+            Some((creep, id))
+        })
+        .min_by_key(|(creep, _)| creep.pos().get_range_to(controller.pos()))
+    else {
+        warn!(
+            "controller in {} is about to downgrade but no creep can upgrade it",
+            base_room.name()
+        );
+        return;
+    };
+
+    // only drop the creep's current task once the upgrade task has been created, otherwise the
+    // creep would sit idle and its work would be thrown away every tick
+    let Some(task) = upgrade::start(&creep, &controller, &mut colony.inventory) else {
+        warn!(
+            "controller in {} is about to downgrade but {} has no energy to upgrade it with",
+            base_room.name(),
+            creep.name()
+        );
+        return;
+    };
+
+    warn!(
+        "controller in {} is about to downgrade, pulling {} off its task to upgrade",
+        base_room.name(),
+        creep.name()
+    );
+    if let Some(old) = colony.tasks.tasks.insert(id, task) {
+        old.abandon(id, &mut colony.inventory, &mut colony.sources);
+    }
+}
+
 /// INVARIANT: `id` must correspond to a creep that exists and it must have a task in `tasks`
-fn execute_task_common(tasks: &mut RoomTasks, id: ObjectId<Creep>, inventory: &mut Inventory) {
+fn execute_task_common(
+    tasks: &mut ColonyTasks,
+    id: ObjectId<Creep>,
+    inventory: &mut Inventory,
+    logistics: &mut Logistics,
+    sources: &mut SourceAssignments,
+) {
     let creep = id.resolve().unwrap();
     let task = tasks.tasks.get_mut(&id).unwrap();
     debug!("executing task {:?} for {}", task, creep.name());
-    match task.execute(inventory, &creep) {
+    movement::register(&creep, task.move_priority());
+    match task.execute(inventory, logistics, &creep) {
         TaskResult::Complete | TaskResult::Error => {
-            tasks.tasks.remove(&id);
+            if let Some(Task::DropHarvest(..)) = tasks.tasks.remove(&id) {
+                sources.release(id);
+            }
         }
         TaskResult::InProgress => {}
     }
 }
 
-fn find_best_task(creep: &Creep, room: &Room, inventory: &mut Inventory) -> Option<Task> {
-    let mut task = None;
-    for body_part in creep.body() {
-        let part = body_part.part();
-        match part {
-            // creeps that can work should be harvesters
-            Part::Work => {
-                let sources = room.find(find::SOURCES, None);
-                let Some(source) = sources.first() else {
-                    // maybe the creep can do something else
-                    continue;
-                };
-                task = Some(Task::DropHarvest(source.id()));
-            }
-            // creeps that can carry should be haulers
-            Part::Carry => {
-                let Some((reservation, target)) = haul::find_target(inventory, &room) else {
-                    break;
-                };
-                task = Some(Task::Haul(HaulState::Gathering, reservation, target));
-            }
-            _ => {}
-        }
-    }
-    task
+/// every kind of task a creep can be assigned when it is idle
+const SCORERS: &[&dyn TaskScorer] = &[
+    &DropHarvestScorer,
+    &HaulScorer,
+    &UpgradeScorer,
+    &BuildScorer,
+    &RepairScorer,
+    &ScoutScorer,
+];
+
+/// picks the highest scoring task that can be created for the creep in `ctx`
+fn find_best_task(ctx: &mut TaskContext) -> Option<Task> {
+    let mut candidates = SCORERS
+        .iter()
+        .filter_map(|scorer| Some((scorer.score(ctx)?, scorer)))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+    trace!(
+        "creep {} task scores: {:?}",
+        ctx.creep.name(),
+        candidates
+            .iter()
+            .map(|(score, _)| score)
+            .collect::<Vec<_>>()
+    );
+
+    candidates
+        .into_iter()
+        .find_map(|(_, scorer)| scorer.create(ctx))
 }
@@ -0,0 +1,116 @@
+use log::*;
+use screeps::{
+    find, prelude::*, ConstructionSite, Creep, ErrorCode, ObjectId, Room, StructureType,
+};
+
+use crate::{
+    colony::Inventory,
+    movement,
+    state::WorkState,
+    tasks::{
+        gather,
+        scoring::{self, TaskContext, TaskScorer},
+        Task, TaskResult,
+    },
+};
+
+/// how much the colony wants construction done when there are sites
+const BUILD_NEED: f32 = 0.5;
+
+/// the range from which a creep can build a construction site
+const BUILD_RANGE: u32 = 3;
+
+pub struct BuildScorer;
+
+impl TaskScorer for BuildScorer {
+    fn score(&self, ctx: &TaskContext) -> Option<f32> {
+        if ctx.body.work == 0 || ctx.body.carry == 0 {
+            return None;
+        }
+
+        let site = find_site(ctx.creep, ctx.rooms)?;
+        let fit = ctx.body.fraction(ctx.body.work + ctx.body.carry);
+        let distance = ctx.creep.pos().get_range_to(site.pos());
+        Some(scoring::utility(fit, BUILD_NEED, distance))
+    }
+
+    fn create(&self, ctx: &mut TaskContext) -> Option<Task> {
+        let site = find_site(ctx.creep, ctx.rooms)?;
+        let id = site.try_id()?;
+        let state = gather::start_work(ctx.creep, ctx.inventory)?;
+        Some(Task::Build(state, id))
+    }
+}
+
+/// finds the construction site that should be built first, breaking ties by distance
+fn find_site(creep: &Creep, rooms: &[Room]) -> Option<ConstructionSite> {
+    rooms
+        .iter()
+        .flat_map(|room| room.find(find::MY_CONSTRUCTION_SITES, None))
+        .min_by_key(|site| {
+            (
+                BuildSortOrder::from(site.structure_type()),
+                creep.pos().get_range_to(site.pos()),
+            )
+        })
+}
+
+pub fn run(
+    state: &mut WorkState,
+    inventory: &mut Inventory,
+    creep: &Creep,
+    site_id: &ObjectId<ConstructionSite>,
+) -> TaskResult {
+    // the site may have been completed or destroyed, by this creep or someone else
+    let Some(site) = site_id.resolve() else {
+        debug!("construction site {} is gone", site_id);
+        if let WorkState::Gathering(reservation_id) = state {
+            inventory.release(*reservation_id);
+        }
+        return TaskResult::Complete;
+    };
+
+    if let Some(result) = gather::gather_energy(state, inventory, creep) {
+        return result;
+    }
+
+    if creep.pos().in_range_to(site.pos(), BUILD_RANGE) {
+        match creep.build(&site) {
+            Ok(()) => TaskResult::InProgress,
+            Err(ErrorCode::NotEnough) => TaskResult::Complete,
+            Err(e) => {
+                warn!(
+                    "creep {} unexpected error {:?} when building",
+                    creep.name(),
+                    e
+                );
+                TaskResult::Error
+            }
+        }
+    } else {
+        movement::travel_to(creep, site.pos(), BUILD_RANGE);
+        TaskResult::InProgress
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// a helper to allow easier sorting of construction sites based on what should be built first.
+enum BuildSortOrder {
+    Spawn,
+    Extension,
+    Tower,
+    Road,
+    Other,
+}
+
+impl From<StructureType> for BuildSortOrder {
+    fn from(value: StructureType) -> Self {
+        match value {
+            StructureType::Spawn => BuildSortOrder::Spawn,
+            StructureType::Extension => BuildSortOrder::Extension,
+            StructureType::Tower => BuildSortOrder::Tower,
+            StructureType::Road => BuildSortOrder::Road,
+            _ => BuildSortOrder::Other,
+        }
+    }
+}
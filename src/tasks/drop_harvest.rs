@@ -1,15 +1,41 @@
 use log::warn;
-use screeps::{Creep, ErrorCode, HasPosition, ObjectId, SharedCreepProperties, Source};
+use screeps::{Creep, ErrorCode, HasPosition, ObjectId, Position, SharedCreepProperties, Source};
 
-use crate::tasks::TaskResult;
+use crate::{
+    movement,
+    tasks::{
+        scoring::{self, TaskContext, TaskScorer},
+        Task, TaskResult,
+    },
+};
 
-pub fn run(source_id: &ObjectId<Source>, creep: &Creep) -> TaskResult {
+pub struct DropHarvestScorer;
+
+impl TaskScorer for DropHarvestScorer {
+    fn score(&self, ctx: &TaskContext) -> Option<f32> {
+        if ctx.body.work == 0 {
+            return None;
+        }
+
+        let (_, tile, unassigned) = ctx.sources.best_slot()?;
+        let fit = ctx.body.fraction(ctx.body.work);
+        let distance = ctx.creep.pos().get_range_to(tile);
+        Some(scoring::utility(fit, unassigned, distance))
+    }
+
+    fn create(&self, ctx: &mut TaskContext) -> Option<Task> {
+        let (source, tile) = ctx.sources.assign(ctx.id, ctx.body.work)?;
+        Some(Task::DropHarvest(source, tile))
+    }
+}
+
+pub fn run(source_id: &ObjectId<Source>, tile: &Position, creep: &Creep) -> TaskResult {
     let creep_pos = creep.pos();
     let Some(source) = source_id.resolve() else {
         warn!("source id {} didn't resolve", source_id);
         return TaskResult::Error;
     };
-    if creep_pos.is_near_to(source.pos()) {
+    if creep_pos == *tile {
         match creep.harvest(&source) {
             Ok(()) | Err(ErrorCode::NotEnough) => TaskResult::InProgress,
             Err(e) => {
@@ -22,7 +48,7 @@ pub fn run(source_id: &ObjectId<Source>, creep: &Creep) -> TaskResult {
             }
         }
     } else {
-        let _ = creep.move_to(&source);
+        movement::travel_to(creep, *tile, 0);
         TaskResult::InProgress
     }
 }
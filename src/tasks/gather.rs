@@ -0,0 +1,152 @@
+//! picking up the resources of a reservation, shared by every task that needs resources
+use log::*;
+use screeps::{game, prelude::*, Creep, ErrorCode, ResourceType, Withdrawable};
+
+use crate::{
+    colony::{Inventory, ReservationId, SelectionPolicy, Target},
+    movement,
+    state::WorkState,
+    tasks::TaskResult,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GatherResult {
+    /// the creep picked up the resources, the reservation is still held
+    Gathered,
+    /// the creep is still on its way to the target
+    InProgress,
+    /// the resources could not be gathered, the reservation has been released
+    Failed,
+}
+
+/// moves `creep` to the target of the reservation and picks up the reserved resources
+pub fn run(
+    inventory: &mut Inventory,
+    creep: &Creep,
+    reservation_id: &ReservationId,
+) -> GatherResult {
+    let Some(reservation) = inventory.resolve_reservation(reservation_id) else {
+        return GatherResult::Failed;
+    };
+    let (kind, amount) = (reservation.resource_type(), reservation.amount());
+
+    let result = match reservation.target() {
+        Target::Resource(id) => {
+            let Some(resource) = id.resolve() else {
+                inventory.release(*reservation_id);
+                return GatherResult::Failed;
+            };
+            if creep.pos().is_near_to(resource.pos()) {
+                match creep.pickup(&resource) {
+                    Ok(()) | Err(ErrorCode::Full) => GatherResult::Gathered,
+                    Err(ErrorCode::InvalidTarget) => {
+                        warn!("target not valid");
+                        GatherResult::Failed
+                    }
+                    Err(e) => {
+                        warn!("unexpected error {:?}", e);
+                        GatherResult::Failed
+                    }
+                }
+            } else {
+                movement::travel_to(creep, resource.pos(), 1);
+                GatherResult::InProgress
+            }
+        }
+        Target::Container(id) => withdraw(creep, id.resolve(), kind, amount),
+        Target::Storage(id) => withdraw(creep, id.resolve(), kind, amount),
+        Target::Terminal(id) => withdraw(creep, id.resolve(), kind, amount),
+        Target::Link(id) => withdraw(creep, id.resolve(), kind, amount),
+        Target::Tombstone(id) => withdraw(creep, id.resolve(), kind, amount),
+        Target::Ruin(id) => withdraw(creep, id.resolve(), kind, amount),
+    };
+
+    if result == GatherResult::Failed {
+        inventory.release(*reservation_id);
+    }
+    result
+}
+
+/// moves `creep` to `target` and withdraws up to `amount` of resource `kind` from it. does not
+/// release the reservation on failure.
+fn withdraw<T>(creep: &Creep, target: Option<T>, kind: ResourceType, amount: u32) -> GatherResult
+where
+    T: Withdrawable + HasStore + HasPosition,
+{
+    let Some(target) = target else {
+        warn!(
+            "creep {} could no longer find its gather target",
+            creep.name()
+        );
+        return GatherResult::Failed;
+    };
+
+    if !creep.pos().is_near_to(target.pos()) {
+        movement::travel_to(creep, target.pos(), 1);
+        return GatherResult::InProgress;
+    }
+
+    // the target may have lost some resources since the reservation was made, so take what is
+    // there instead of failing
+    let amount = amount
+        .min(creep.store().get_free_capacity(Some(kind)).max(0) as u32)
+        .min(target.store().get_used_capacity(Some(kind)));
+    if amount == 0 {
+        return GatherResult::Failed;
+    }
+
+    match creep.withdraw(&target, kind, Some(amount)) {
+        Ok(()) | Err(ErrorCode::Full) => GatherResult::Gathered,
+        Err(e) => {
+            warn!("unexpected error {:?}", e);
+            GatherResult::Failed
+        }
+    }
+}
+
+/// the initial state of a task that spends energy, reserving enough energy to fill `creep` if it
+/// is empty. if no target has that much, whatever the best target has is reserved instead.
+pub fn start_work(creep: &Creep, inventory: &mut Inventory) -> Option<WorkState> {
+    if creep.store().get_used_capacity(Some(ResourceType::Energy)) > 0 {
+        Some(WorkState::Working)
+    } else {
+        let amount = creep.store().get_free_capacity(Some(ResourceType::Energy)) as u32;
+        let reservation = inventory
+            .request_partial(
+                creep.try_id()?,
+                ResourceType::Energy,
+                amount,
+                creep.pos(),
+                SelectionPolicy::Nearest,
+                game::time(),
+            )
+            .ok()?;
+        Some(WorkState::Gathering(reservation))
+    }
+}
+
+/// runs the gathering step of a task that spends energy. returns the result of the task for this
+/// tick while gathering, or `None` if the creep has energy and should work.
+pub fn gather_energy(
+    state: &mut WorkState,
+    inventory: &mut Inventory,
+    creep: &Creep,
+) -> Option<TaskResult> {
+    let WorkState::Gathering(reservation_id) = state else {
+        if creep.store().get_used_capacity(Some(ResourceType::Energy)) == 0 {
+            return Some(TaskResult::Complete);
+        }
+        return None;
+    };
+
+    match run(inventory, creep, reservation_id) {
+        GatherResult::Gathered => {
+            // the energy is in the creep now, nobody else can take it
+            inventory.release(*reservation_id);
+            *state = WorkState::Working;
+            Some(TaskResult::InProgress)
+        }
+        GatherResult::InProgress => Some(TaskResult::InProgress),
+        GatherResult::Failed => Some(TaskResult::Error),
+    }
+}
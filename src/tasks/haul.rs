@@ -1,152 +1,251 @@
 use log::*;
-use screeps::{
-    find, game, prelude::*, Creep, ErrorCode, RawObjectId, Room, StoreObject, StructureObject,
-    StructureType,
-};
+use screeps::{game, prelude::*, Creep, ErrorCode, RawObjectId, ResourceType, StructureObject};
 use wasm_bindgen::JsValue;
 
 use crate::{
-    colony::{Inventory, ReservationId, Target},
+    colony::{
+        logistics::{Assignment, Logistics},
+        Inventory, ReservationId, SelectionPolicy,
+    },
+    movement,
     state::HaulState,
-    tasks::TaskResult,
+    tasks::{
+        gather::{self, GatherResult},
+        scoring::{self, TaskContext, TaskScorer},
+        Task, TaskResult,
+    },
 };
 
-pub fn find_target(inventory: &mut Inventory, room: &Room) -> Option<(ReservationId, RawObjectId)> {
-    let structures = room.find(find::MY_STRUCTURES, None);
-    let mut structures = structures
-        .into_iter()
-        .filter_map(|s| {
-            let store_object = StoreObject::try_from(s.clone()).ok()?;
-            let resource_types = store_object.resource_types();
-
-            resource_types
-                .iter()
-                .any(|&ty| store_object.store().get_free_capacity(Some(ty)) > 0)
-                .then_some((s, resource_types))
-        })
-        .collect::<Vec<_>>();
-    structures.sort_by_key(|s| TargetSortOrder::from(&s.0));
-
-    if let Some((target, resource_types)) = structures.first() {
-        let amount = 50;
-        let Some(reservation_id) = resource_types
-            .iter()
-            .find_map(|&kind| inventory.request(kind, amount).ok())
-        else {
-            debug!(
-                "unable to reserve resources for {}",
-                target.structure_type()
-            );
+pub struct HaulScorer;
+
+impl TaskScorer for HaulScorer {
+    fn score(&self, ctx: &TaskContext) -> Option<f32> {
+        if ctx.body.carry == 0 {
             return None;
+        }
+
+        let assignment = ctx.logistics.assignment(ctx.id)?;
+        let capacity = ctx.creep.store().get_capacity(None).max(1);
+        let fill = (assignment.amount as f32 / capacity as f32).min(1.0);
+        let need = assignment.request.priority.need() * fill;
+        let fit = ctx.body.fraction(ctx.body.carry);
+        Some(scoring::utility(fit, need, assignment.distance))
+    }
+
+    fn create(&self, ctx: &mut TaskContext) -> Option<Task> {
+        let owner = ctx.id;
+        let now = game::time();
+        let assignment = *ctx.logistics.assignment(owner)?;
+        let Assignment {
+            provide,
+            request,
+            amount,
+            ..
+        } = assignment;
+
+        // a loaded hauler delivers what it is already carrying
+        let Some(provide) = provide else {
+            let delivery =
+                ctx.inventory
+                    .reserve_delivery(owner, request.sink, request.kind, amount, now);
+            return Some(Task::Haul(
+                HaulState::Delivering(request.kind),
+                delivery,
+                request.sink,
+            ));
         };
 
-        debug!("got reservation {:?}", reservation_id);
+        // the provide may have been taken since the orders were matched, if so gather the same
+        // amount from whatever is close to it instead
+        let reserved = ctx
+            .inventory
+            .request_from(owner, provide.target, provide.kind, amount, now);
+        let reservations = match reserved {
+            Ok(reservation) => vec![reservation],
+            Err(e) => {
+                debug!("unable to reserve {:?} for haul: {:?}", provide.target, e);
+                let policy = SelectionPolicy::Balanced;
+                ctx.inventory
+                    .request_split(owner, provide.kind, amount, provide.pos, policy, now)
+                    .ok()?
+            }
+        };
+        let amount = reservations
+            .iter()
+            .filter_map(|id| ctx.inventory.resolve_reservation(id))
+            .map(|reservation| reservation.amount())
+            .sum();
+        let delivery =
+            ctx.inventory
+                .reserve_delivery(owner, request.sink, request.kind, amount, now);
 
-        let id = target.as_structure().raw_id();
-        Some((reservation_id, id))
-    } else {
-        warn!("no haul target found");
-        None
+        Some(Task::Haul(
+            HaulState::Gathering(provide.kind, reservations),
+            delivery,
+            request.sink,
+        ))
     }
 }
 
+/// runs a haul task, releasing the `delivery` reservation once the task ends
 pub fn run(
     state: &mut HaulState,
     inventory: &mut Inventory,
+    logistics: &mut Logistics,
     creep: &Creep,
-    reservation_id: &ReservationId,
-    target: &RawObjectId,
+    delivery: &mut ReservationId,
+    target: &mut RawObjectId,
 ) -> TaskResult {
-    let Some(reservation) = inventory.resolve_reservation(reservation_id) else {
-        return TaskResult::Error;
-    };
+    let result = step(state, inventory, logistics, creep, delivery, target);
+    if result != TaskResult::InProgress {
+        inventory.release(*delivery);
+    }
+    result
+}
 
+fn step(
+    state: &mut HaulState,
+    inventory: &mut Inventory,
+    logistics: &mut Logistics,
+    creep: &Creep,
+    delivery: &mut ReservationId,
+    target: &mut RawObjectId,
+) -> TaskResult {
     match state {
-        HaulState::Gathering => match reservation.target() {
-            Target::Resource(id) => {
-                // resolving a reservation ensures that the target exists
-                let resource = id.resolve().unwrap();
-                if creep.pos().is_near_to(resource.pos()) {
-                    match creep.pickup(&resource) {
-                        Ok(()) | Err(ErrorCode::Full) => {
-                            *state = HaulState::Delivering;
-                            TaskResult::InProgress
-                        }
-                        Err(ErrorCode::InvalidTarget) => {
-                            warn!("target not valid");
-                            inventory.release(*reservation_id);
-                            TaskResult::Error
-                        }
-                        Err(e) => {
-                            warn!("unexpected error {:?}", e);
-                            inventory.release(*reservation_id);
-                            TaskResult::Error
-                        }
-                    }
-                } else {
-                    let _ = creep.move_to(resource);
-                    TaskResult::InProgress
+        HaulState::Gathering(kind, reservations) => {
+            let kind = *kind;
+
+            // the store only reflects what was gathered on earlier ticks, so a full creep is
+            // noticed here rather than right after the withdraw that filled it
+            if creep.store().get_free_capacity(Some(kind)) <= 0 {
+                for reservation_id in reservations.drain(..) {
+                    inventory.release(reservation_id);
+                }
+                *state = HaulState::Delivering(kind);
+                return TaskResult::InProgress;
+            }
+
+            let Some(&reservation_id) = reservations.first() else {
+                return TaskResult::Error;
+            };
+
+            let gathered = match gather::run(inventory, creep, &reservation_id) {
+                GatherResult::Gathered => {
+                    // the resources are in the creep now, nobody else can take them
+                    inventory.release(reservation_id);
+                    reservations.remove(0);
+                    true
+                }
+                GatherResult::InProgress => return TaskResult::InProgress,
+                // the reservation has already been released, try the next one
+                GatherResult::Failed => {
+                    reservations.remove(0);
+                    false
                 }
+            };
+            if !reservations.is_empty() {
+                return TaskResult::InProgress;
             }
-            Target::Storage(id) => todo!(),
-        },
-        HaulState::Delivering => {
-            let Some(target) = game::get_object_by_id_erased(target) else {
+
+            // resources gathered this tick only show up in the store at the end of the tick
+            if gathered || creep.store().get_used_capacity(Some(kind)) > 0 {
+                *state = HaulState::Delivering(kind);
+                TaskResult::InProgress
+            } else {
+                TaskResult::Error
+            }
+        }
+        HaulState::Delivering(kind) => {
+            let kind = *kind;
+            let Some(object) = game::get_object_by_id_erased(target) else {
                 warn!("creep {} could no longer find {}", creep.name(), target);
-                inventory.release(*reservation_id);
                 return TaskResult::Error;
             };
-            let structure = StructureObject::from(JsValue::from(target));
+            let structure = StructureObject::from(JsValue::from(object));
             let Some(transferrable) = structure.as_transferable() else {
                 warn!("structure was not transferrable");
-                inventory.release(*reservation_id);
                 return TaskResult::Error;
             };
 
             if creep.pos().is_near_to(structure.pos()) {
-                match creep.transfer(transferrable, reservation.resource_type(), None) {
+                let held = creep.store().get_used_capacity(Some(kind));
+                match creep.transfer(transferrable, kind, None) {
                     Ok(()) => {
-                        inventory.release(*reservation_id);
-                        TaskResult::Complete
+                        // the store of the target doesn't include this transfer yet
+                        let room = structure.as_has_store().map_or(0, |target| {
+                            target.store().get_free_capacity(Some(kind)).max(0) as u32
+                        });
+                        if held <= room {
+                            return TaskResult::Complete;
+                        }
+                        // only part of the load fit, the rest goes somewhere else
+                        let rest = held - room;
+                        redirect(inventory, logistics, creep, kind, rest, delivery, target)
                     }
                     Err(ErrorCode::Full) => {
-                        // TODO: handle this by returning resources somewhere maybe?
-                        inventory.release(*reservation_id);
-                        TaskResult::Error
+                        redirect(inventory, logistics, creep, kind, held, delivery, target)
                     }
+                    // the creep is already empty
+                    Err(ErrorCode::NotEnough) => TaskResult::Complete,
                     Err(e) => {
                         warn!("unexpected error {:?}", e);
-                        inventory.release(*reservation_id);
                         TaskResult::Error
                     }
                 }
-                // TODO: if empty, exit
             } else {
-                let _ = creep.move_to(structure);
+                movement::travel_to(creep, structure.pos(), 1);
                 TaskResult::InProgress
             }
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-/// a helper to allow easier sorting of structures based on what should be transferred to first.
-enum TargetSortOrder {
-    Spawn,
-    Extension,
-    Tower,
-    Storage,
-    Other,
-}
-
-impl From<&StructureObject> for TargetSortOrder {
-    fn from(value: &StructureObject) -> Self {
-        match value.structure_type() {
-            StructureType::Spawn => TargetSortOrder::Spawn,
-            StructureType::Extension => TargetSortOrder::Extension,
-            StructureType::Tower => TargetSortOrder::Tower,
-            StructureType::Storage => TargetSortOrder::Storage,
-            _ => TargetSortOrder::Other,
+/// sends a creep that still holds `amount` of resource `kind` after delivering to `target` on to
+/// the next sink that needs it, replacing its delivery reservation. if no sink needs it, the
+/// creep falls back to the storage in its room, and drops the resources if that is full too.
+fn redirect(
+    inventory: &mut Inventory,
+    logistics: &mut Logistics,
+    creep: &Creep,
+    kind: ResourceType,
+    amount: u32,
+    delivery: &mut ReservationId,
+    target: &mut RawObjectId,
+) -> TaskResult {
+    let Some(owner) = creep.try_id() else {
+        return TaskResult::Error;
+    };
+    let sink = match logistics.redirect(creep.pos(), kind, amount, *target) {
+        Some(request) => Some((request.sink, amount.min(request.amount))),
+        None => creep
+            .room()
+            .and_then(|room| room.storage())
+            .filter(|storage| storage.store().get_free_capacity(Some(kind)) > 0)
+            .map(|storage| storage.raw_id())
+            .filter(|storage| *storage != *target)
+            .map(|storage| (storage, amount)),
+    };
+    let Some((sink, amount)) = sink else {
+        // dropped resources are offered to haulers again once a sink opens up, which keeps this
+        // hauler from idling with a full load until then
+        debug!(
+            "creep {} has nowhere to deliver its remaining {:?}, dropping it",
+            creep.name(),
+            kind
+        );
+        if let Err(e) = creep.drop(kind, Some(amount)) {
+            warn!(
+                "creep {} was unable to drop {:?}: {:?}",
+                creep.name(),
+                kind,
+                e
+            );
         }
-    }
+        return TaskResult::Complete;
+    };
+
+    inventory.release(*delivery);
+    *delivery = inventory.reserve_delivery(owner, sink, kind, amount, game::time());
+    *target = sink;
+    TaskResult::InProgress
 }
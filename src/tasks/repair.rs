@@ -0,0 +1,116 @@
+use log::*;
+use screeps::{find, game, prelude::*, Creep, ErrorCode, RawObjectId, StructureObject};
+use wasm_bindgen::JsValue;
+
+use crate::{
+    colony::Inventory,
+    movement,
+    state::WorkState,
+    tasks::{
+        gather,
+        scoring::{self, TaskContext, TaskScorer},
+        Task, TaskResult,
+    },
+};
+
+/// how much the colony wants repairs done, scaled by the priority of the structure
+const REPAIR_NEED: f32 = 0.4;
+
+/// the range from which a creep can repair a structure
+const REPAIR_RANGE: u32 = 3;
+
+pub struct RepairScorer;
+
+impl TaskScorer for RepairScorer {
+    fn score(&self, ctx: &TaskContext) -> Option<f32> {
+        if ctx.body.work == 0 || ctx.body.carry == 0 {
+            return None;
+        }
+
+        let (structure, priority) = find_structure(ctx)?;
+        let fit = ctx.body.fraction(ctx.body.work + ctx.body.carry);
+        let need = REPAIR_NEED * priority.min(1.0);
+        let distance = ctx.creep.pos().get_range_to(structure.pos());
+        Some(scoring::utility(fit, need, distance))
+    }
+
+    fn create(&self, ctx: &mut TaskContext) -> Option<Task> {
+        let (structure, _) = find_structure(ctx)?;
+        let goal = ctx.repair.config.goal_hits(&structure);
+        let state = gather::start_work(ctx.creep, ctx.inventory)?;
+        ctx.repair.start_repair(&structure);
+        Some(Task::Repair(state, structure.as_structure().raw_id(), goal))
+    }
+}
+
+/// finds the structure that most urgently needs repairs, breaking ties by distance
+fn find_structure(ctx: &TaskContext) -> Option<(StructureObject, f32)> {
+    ctx.rooms
+        .iter()
+        .flat_map(|room| room.find(find::STRUCTURES, None))
+        .filter_map(|structure| {
+            let priority = ctx.repair.priority(&structure, ctx.sources)?;
+            Some((structure, priority))
+        })
+        .max_by(|a, b| {
+            a.1.total_cmp(&b.1).then_with(|| {
+                // closer is better, so compare in reverse
+                let creep_pos = ctx.creep.pos();
+                creep_pos
+                    .get_range_to(b.0.pos())
+                    .cmp(&creep_pos.get_range_to(a.0.pos()))
+            })
+        })
+}
+
+pub fn run(
+    state: &mut WorkState,
+    inventory: &mut Inventory,
+    creep: &Creep,
+    target: &RawObjectId,
+    goal: u32,
+) -> TaskResult {
+    let Some(target) = game::get_object_by_id_erased(target) else {
+        debug!("repair target {} is gone", target);
+        if let WorkState::Gathering(reservation_id) = state {
+            inventory.release(*reservation_id);
+        }
+        return TaskResult::Complete;
+    };
+    let structure = StructureObject::from(JsValue::from(target));
+
+    // keep repairing until the high watermark so that creeps don't come back for every hit lost
+    if structure.as_structure().hits() >= goal {
+        if let WorkState::Gathering(reservation_id) = state {
+            inventory.release(*reservation_id);
+        }
+        return TaskResult::Complete;
+    }
+
+    if let Some(result) = gather::gather_energy(state, inventory, creep) {
+        return result;
+    }
+
+    let Some(repairable) = structure.as_repairable() else {
+        warn!("structure was not repairable");
+        return TaskResult::Error;
+    };
+
+    if creep.pos().in_range_to(structure.pos(), REPAIR_RANGE) {
+        match creep.repair(repairable) {
+            Ok(()) => TaskResult::InProgress,
+            Err(ErrorCode::NotEnough) => TaskResult::Complete,
+            Err(e) => {
+                warn!(
+                    "creep {} unexpected error {:?} when repairing",
+                    creep.name(),
+                    e
+                );
+                TaskResult::Error
+            }
+        }
+    } else {
+        movement::travel_to(creep, structure.pos(), REPAIR_RANGE);
+        TaskResult::InProgress
+    }
+}
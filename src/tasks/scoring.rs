@@ -0,0 +1,81 @@
+//! utility scoring used to pick the task a creep is best suited for
+//!
+//! every kind of task has a [`TaskScorer`] that rates how useful it would be for a creep to take
+//! that task right now. the creep is given the highest scoring task that can actually be created.
+
+use screeps::{Creep, ObjectId, Part, Room};
+
+use crate::{
+    colony::{logistics::Logistics, repair::RepairState, sources::SourceAssignments, Inventory},
+    tasks::Task,
+};
+
+/// the distance at which a task's score is halved
+const DISTANCE_FALLOFF: f32 = 25.0;
+
+/// the number of each relevant body part a creep has
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BodyComposition {
+    pub work: u32,
+    pub carry: u32,
+    pub moves: u32,
+    pub total: u32,
+}
+
+impl BodyComposition {
+    pub fn new(creep: &Creep) -> Self {
+        let mut body = Self::default();
+        for body_part in creep.body() {
+            match body_part.part() {
+                Part::Work => body.work += 1,
+                Part::Carry => body.carry += 1,
+                Part::Move => body.moves += 1,
+                _ => {}
+            }
+            body.total += 1;
+        }
+        body
+    }
+
+    /// the fraction of the non-MOVE parts of the body that are `count` parts
+    pub fn fraction(&self, count: u32) -> f32 {
+        let working = self.total - self.moves;
+        if working == 0 {
+            0.0
+        } else {
+            count as f32 / working as f32
+        }
+    }
+}
+
+/// everything that scorers can use to evaluate and create tasks for a single creep
+pub struct TaskContext<'a> {
+    pub creep: &'a Creep,
+    pub id: ObjectId<Creep>,
+    pub body: BodyComposition,
+    pub base_room: &'a Room,
+    /// every visible room in the creep's colony
+    pub rooms: &'a [Room],
+    pub inventory: &'a mut Inventory,
+    pub sources: &'a mut SourceAssignments,
+    pub repair: &'a mut RepairState,
+    pub logistics: &'a Logistics,
+}
+
+pub trait TaskScorer {
+    /// rates how useful it would be for the creep to take this kind of task, higher is better.
+    /// returns `None` if the creep cannot do this task at all.
+    ///
+    /// this must not change any state, since the task may not be chosen.
+    fn score(&self, ctx: &TaskContext) -> Option<f32>;
+
+    /// creates the task, making any reservations that it needs. this may still fail if the
+    /// task turns out to be impossible.
+    fn create(&self, ctx: &mut TaskContext) -> Option<Task>;
+}
+
+/// combines the parts of a score. `fit` is how well suited the creep's body is to the task,
+/// `need` is how much the colony needs the task done, both from 0 to 1.
+pub fn utility(fit: f32, need: f32, distance: u32) -> f32 {
+    fit * need * DISTANCE_FALLOFF / (DISTANCE_FALLOFF + distance as f32)
+}
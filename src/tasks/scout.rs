@@ -0,0 +1,55 @@
+use log::{debug, warn};
+use screeps::{Creep, Position, RoomCoordinate, RoomName, SharedCreepProperties};
+
+use crate::{
+    intel,
+    movement::{self, TravelResult},
+    tasks::{
+        scoring::{TaskContext, TaskScorer},
+        Task, TaskResult,
+    },
+};
+
+/// how close to the center of a room a scout goes, far enough in to not bounce between rooms
+const CENTER_RANGE: u32 = 20;
+
+pub struct ScoutScorer;
+
+impl TaskScorer for ScoutScorer {
+    fn score(&self, ctx: &TaskContext) -> Option<f32> {
+        // only creeps with nothing but MOVE parts scout, everything else has real work to do
+        if ctx.body.total == 0 || ctx.body.moves != ctx.body.total {
+            return None;
+        }
+        Some(1.0)
+    }
+
+    fn create(&self, ctx: &mut TaskContext) -> Option<Task> {
+        let room_name = intel::scout_target(ctx.base_room.name())?;
+        Some(Task::Scout(room_name))
+    }
+}
+
+pub fn run(room_name: &RoomName, creep: &Creep) -> TaskResult {
+    // intel is gathered from every visible room at the start of each tick, so being in the room
+    // is enough
+    if creep.room().is_some_and(|room| room.name() == *room_name) {
+        debug!("creep {} scouted {}", creep.name(), room_name);
+        return TaskResult::Complete;
+    }
+
+    let center = RoomCoordinate::new(25).unwrap();
+    let target = Position::new(center, center, *room_name);
+    match movement::travel_to(creep, target, CENTER_RANGE) {
+        TravelResult::NoPath => {
+            warn!(
+                "creep {} unable to reach {} to scout it",
+                creep.name(),
+                room_name
+            );
+            intel::mark_unreachable(*room_name);
+            TaskResult::Error
+        }
+        TravelResult::Arrived | TravelResult::Moving => TaskResult::InProgress,
+    }
+}
@@ -0,0 +1,104 @@
+use log::*;
+use screeps::{prelude::*, Creep, ErrorCode, ObjectId, StructureController};
+
+use crate::{
+    colony::Inventory,
+    movement,
+    state::WorkState,
+    tasks::{
+        gather,
+        scoring::{self, TaskContext, TaskScorer},
+        Task, TaskResult,
+    },
+};
+
+/// when the controller has fewer ticks than this until it downgrades, upgrading preempts all
+/// other work
+pub const DOWNGRADE_THRESHOLD: u32 = 5_000;
+
+/// how much the colony wants its controller upgraded when it is in no danger of downgrading
+const BASE_NEED: f32 = 0.3;
+/// how much the colony wants its controller upgraded when it is close to downgrading, high enough
+/// to beat every other task
+const PROTECT_NEED: f32 = 100.0;
+
+/// the range from which a creep can upgrade a controller
+const UPGRADE_RANGE: u32 = 3;
+
+pub struct UpgradeScorer;
+
+impl TaskScorer for UpgradeScorer {
+    fn score(&self, ctx: &TaskContext) -> Option<f32> {
+        if ctx.body.work == 0 || ctx.body.carry == 0 {
+            return None;
+        }
+
+        let controller = ctx.base_room.controller()?;
+        let need = if needs_protection(&controller) {
+            PROTECT_NEED
+        } else {
+            BASE_NEED
+        };
+        let fit = ctx.body.fraction(ctx.body.work + ctx.body.carry);
+        let distance = ctx.creep.pos().get_range_to(controller.pos());
+        Some(scoring::utility(fit, need, distance))
+    }
+
+    fn create(&self, ctx: &mut TaskContext) -> Option<Task> {
+        let controller = ctx.base_room.controller()?;
+        start(ctx.creep, &controller, ctx.inventory)
+    }
+}
+
+/// whether the controller is close enough to downgrading that upgrading it should preempt
+/// other work
+pub fn needs_protection(controller: &StructureController) -> bool {
+    controller.my()
+        && controller
+            .ticks_to_downgrade()
+            .is_some_and(|ticks| ticks < DOWNGRADE_THRESHOLD)
+}
+
+/// creates an upgrade task for `creep`, reserving energy if the creep does not have any
+pub fn start(
+    creep: &Creep,
+    controller: &StructureController,
+    inventory: &mut Inventory,
+) -> Option<Task> {
+    let state = gather::start_work(creep, inventory)?;
+    Some(Task::Upgrade(state, controller.id()))
+}
+
+pub fn run(
+    state: &mut WorkState,
+    inventory: &mut Inventory,
+    creep: &Creep,
+    controller_id: &ObjectId<StructureController>,
+) -> TaskResult {
+    if let Some(result) = gather::gather_energy(state, inventory, creep) {
+        return result;
+    }
+
+    let Some(controller) = controller_id.resolve() else {
+        warn!("controller {} didn't resolve", controller_id);
+        return TaskResult::Error;
+    };
+
+    if creep.pos().in_range_to(controller.pos(), UPGRADE_RANGE) {
+        match creep.upgrade_controller(&controller) {
+            Ok(()) => TaskResult::InProgress,
+            Err(ErrorCode::NotEnough) => TaskResult::Complete,
+            Err(e) => {
+                warn!(
+                    "creep {} unexpected error {:?} when upgrading",
+                    creep.name(),
+                    e
+                );
+                TaskResult::Error
+            }
+        }
+    } else {
+        movement::travel_to(creep, controller.pos(), UPGRADE_RANGE);
+        TaskResult::InProgress
+    }
+}
@@ -1 +1,636 @@
+//! terrain analysis over a single room
+//!
+//! everything here works on a plain [`TerrainGrid`] instead of game objects, so it can be used
+//! with terrain strings outside of the game as well as with live rooms.
 
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+};
+
+use screeps::Position;
+use serde::{Deserialize, Serialize};
+
+/// the width and height of a room
+pub const ROOM_SIZE: u8 = 50;
+
+/// the number of tiles in a room
+pub const ROOM_AREA: usize = ROOM_SIZE as usize * ROOM_SIZE as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TileTerrain {
+    Plain,
+    Swamp,
+    Wall,
+}
+
+/// the terrain of a single room
+#[derive(Debug, Clone)]
+pub struct TerrainGrid {
+    tiles: Vec<TileTerrain>,
+}
+
+impl TerrainGrid {
+    /// creates a grid from the raw terrain buffer of a room, using the game's terrain masks
+    pub fn from_raw(raw: &[u8]) -> Result<Self, TerrainError> {
+        if raw.len() != ROOM_AREA {
+            return Err(TerrainError::InvalidLength(raw.len()));
+        }
+
+        let tiles = raw
+            .iter()
+            .map(|&mask| {
+                if mask & 1 != 0 {
+                    TileTerrain::Wall
+                } else if mask & 2 != 0 {
+                    TileTerrain::Swamp
+                } else {
+                    TileTerrain::Plain
+                }
+            })
+            .collect();
+        Ok(Self { tiles })
+    }
+
+    /// creates a grid from a terrain string, one digit per tile in row major order, with the
+    /// same values as the raw terrain buffer
+    pub fn from_terrain_string(terrain: &str) -> Result<Self, TerrainError> {
+        let raw = terrain
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| {
+                c.to_digit(10)
+                    .filter(|&d| d <= 3)
+                    .map(|d| d as u8)
+                    .ok_or(TerrainError::InvalidChar(c))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::from_raw(&raw)
+    }
+
+    pub fn get(&self, tile: Tile) -> TileTerrain {
+        self.tiles[tile.index()]
+    }
+
+    pub fn is_walkable(&self, tile: Tile) -> bool {
+        self.get(tile) != TileTerrain::Wall
+    }
+
+    /// every walkable tile on the edge of the room
+    pub fn exits(&self) -> Vec<Tile> {
+        (0..ROOM_AREA)
+            .map(Tile::from_index)
+            .filter(|&tile| tile.is_edge() && self.is_walkable(tile))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TerrainError {
+    InvalidLength(usize),
+    InvalidChar(char),
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerrainError::InvalidLength(len) => {
+                write!(f, "terrain had {} tiles instead of {}", len, ROOM_AREA)
+            }
+            TerrainError::InvalidChar(c) => write!(f, "invalid terrain character {:?}", c),
+        }
+    }
+}
+
+/// a position within a room
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Tile {
+    pub x: u8,
+    pub y: u8,
+}
+
+impl Tile {
+    pub fn new(x: u8, y: u8) -> Self {
+        Self { x, y }
+    }
+
+    pub fn from_index(idx: usize) -> Self {
+        Self {
+            x: (idx % usize::from(ROOM_SIZE)) as u8,
+            y: (idx / usize::from(ROOM_SIZE)) as u8,
+        }
+    }
+
+    /// the index of the tile in row major order
+    pub fn index(self) -> usize {
+        usize::from(self.y) * usize::from(ROOM_SIZE) + usize::from(self.x)
+    }
+
+    /// the tile offset by `(dx, dy)`, if it is inside the room
+    pub fn offset(self, dx: i8, dy: i8) -> Option<Self> {
+        let x = self.x.checked_add_signed(dx)?;
+        let y = self.y.checked_add_signed(dy)?;
+        (x < ROOM_SIZE && y < ROOM_SIZE).then_some(Self { x, y })
+    }
+
+    /// all tiles in the room next to this one, including diagonals
+    pub fn neighbors(self) -> impl Iterator<Item = Tile> {
+        const OFFSETS: [(i8, i8); 8] = [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ];
+        OFFSETS
+            .into_iter()
+            .filter_map(move |(dx, dy)| self.offset(dx, dy))
+    }
+
+    /// all tiles in the room orthogonally next to this one
+    pub fn orthogonal_neighbors(self) -> impl Iterator<Item = Tile> {
+        [(0, -1), (-1, 0), (1, 0), (0, 1)]
+            .into_iter()
+            .filter_map(move |(dx, dy)| self.offset(dx, dy))
+    }
+
+    /// the number of moves between two tiles, ignoring terrain
+    pub fn range_to(self, other: Tile) -> u8 {
+        self.x.abs_diff(other.x).max(self.y.abs_diff(other.y))
+    }
+
+    /// whether the tile is on the edge of the room
+    pub fn is_edge(self) -> bool {
+        self.x == 0 || self.y == 0 || self.x == ROOM_SIZE - 1 || self.y == ROOM_SIZE - 1
+    }
+}
+
+impl From<Position> for Tile {
+    fn from(pos: Position) -> Self {
+        Self::new(pos.x().u8(), pos.y().u8())
+    }
+}
+
+impl fmt::Display for Tile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+/// every tile at exactly `radius` moves from `center`
+pub fn ring(center: Tile, radius: i8) -> impl Iterator<Item = Tile> {
+    (-radius..=radius)
+        .flat_map(move |dx| (-radius..=radius).map(move |dy| (dx, dy)))
+        .filter(move |(dx, dy)| i8::max(dx.abs(), dy.abs()) == radius)
+        .filter_map(move |(dx, dy)| center.offset(dx, dy))
+}
+
+/// the number of moves to reach every tile from `starts`, `u32::MAX` if it is unreachable
+pub fn walking_distance(terrain: &TerrainGrid, starts: &[Tile]) -> Vec<u32> {
+    let mut distance = vec![u32::MAX; ROOM_AREA];
+    let mut queue = VecDeque::new();
+    for &start in starts {
+        distance[start.index()] = 0;
+        queue.push_back(start);
+    }
+
+    while let Some(tile) = queue.pop_front() {
+        let next = distance[tile.index()] + 1;
+        for neighbor in tile.neighbors() {
+            if terrain.is_walkable(neighbor) && distance[neighbor.index()] == u32::MAX {
+                distance[neighbor.index()] = next;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    distance
+}
+
+/// every tile that can be reached from `starts` without stepping on walls or tiles where
+/// `passable` is false. the starting tiles are always reached.
+pub fn flood_fill(
+    terrain: &TerrainGrid,
+    starts: &[Tile],
+    passable: impl Fn(Tile) -> bool,
+) -> Vec<bool> {
+    let mut reached = vec![false; ROOM_AREA];
+    let mut queue = VecDeque::new();
+    for &start in starts {
+        reached[start.index()] = true;
+        queue.push_back(start);
+    }
+
+    while let Some(tile) = queue.pop_front() {
+        for neighbor in tile.neighbors() {
+            if !reached[neighbor.index()] && terrain.is_walkable(neighbor) && passable(neighbor) {
+                reached[neighbor.index()] = true;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+    reached
+}
+
+/// every tile that hostile creeps entering the room could walk to, when they can't pass through
+/// `blocked` tiles
+pub fn reachable_from_exits(terrain: &TerrainGrid, blocked: &HashSet<Tile>) -> Vec<bool> {
+    let exits = terrain
+        .exits()
+        .into_iter()
+        .filter(|tile| !blocked.contains(tile))
+        .collect::<Vec<_>>();
+    flood_fill(terrain, &exits, |tile| !blocked.contains(&tile))
+}
+
+/// the distance from every tile to the closest wall or room edge, counting diagonals as one
+pub fn distance_transform(terrain: &TerrainGrid) -> Vec<u8> {
+    let mut dist = (0..ROOM_AREA)
+        .map(Tile::from_index)
+        .map(|tile| {
+            if tile.is_edge() || !terrain.is_walkable(tile) {
+                0
+            } else {
+                u8::MAX
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // forward pass looks at the tiles above and to the left, backward pass below and to the right
+    for idx in 0..dist.len() {
+        let tile = Tile::from_index(idx);
+        for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0)] {
+            if let Some(neighbor) = tile.offset(dx, dy) {
+                dist[idx] = dist[idx].min(dist[neighbor.index()].saturating_add(1));
+            }
+        }
+    }
+    for idx in (0..dist.len()).rev() {
+        let tile = Tile::from_index(idx);
+        for (dx, dy) in [(1, 1), (0, 1), (-1, 1), (1, 0)] {
+            if let Some(neighbor) = tile.offset(dx, dy) {
+                dist[idx] = dist[idx].min(dist[neighbor.index()].saturating_add(1));
+            }
+        }
+    }
+
+    dist
+}
+
+/// groups of connected tiles that are all at least `min_distance` from any wall or room edge,
+/// largest first. a square of side `2 * min_distance - 1` fits around every tile in an area.
+pub fn open_areas(terrain: &TerrainGrid, min_distance: u8) -> Vec<Vec<Tile>> {
+    let dist = distance_transform(terrain);
+    let is_open = |tile: Tile| dist[tile.index()] >= min_distance;
+
+    let mut seen = vec![false; ROOM_AREA];
+    let mut areas = Vec::new();
+    for start in (0..ROOM_AREA).map(Tile::from_index) {
+        if seen[start.index()] || !is_open(start) {
+            continue;
+        }
+
+        let reached = flood_fill(terrain, &[start], is_open);
+        let area = (0..ROOM_AREA)
+            .filter(|&idx| reached[idx])
+            .map(Tile::from_index)
+            .collect::<Vec<_>>();
+        for tile in &area {
+            seen[tile.index()] = true;
+        }
+        areas.push(area);
+    }
+
+    // stable, so areas of the same size stay in scan order
+    areas.sort_by_key(|area| std::cmp::Reverse(area.len()));
+    areas
+}
+
+/// the capacity of edges that can never be cut
+const UNCUTTABLE: u32 = u32::MAX / 4;
+
+/// finds the cheapest set of tiles that separates every tile in `protect` from the room exits,
+/// where blocking a tile costs `cost(tile)`. tiles in `protect` are never part of the cut, and
+/// neither are tiles that can't be built on because they are on or next to an exit.
+///
+/// returns `None` if `protect` can't be separated from the exits, otherwise the tiles of the cut
+/// in row major order. the result only depends on the inputs.
+pub fn min_cut(
+    terrain: &TerrainGrid,
+    protect: &[Tile],
+    cost: impl Fn(Tile) -> u32,
+) -> Option<Vec<Tile>> {
+    let protected = protect.iter().copied().collect::<HashSet<_>>();
+    let exits = terrain.exits();
+    let near_exit = |tile: Tile| exits.iter().any(|&exit| exit.range_to(tile) <= 1);
+
+    // every tile is split into an in node and an out node, joined by an edge with the cost of
+    // blocking the tile
+    let tile_in = |tile: Tile| tile.index() * 2;
+    let tile_out = |tile: Tile| tile.index() * 2 + 1;
+    let source = ROOM_AREA * 2;
+    let sink = source + 1;
+    let mut graph = FlowGraph::new(sink + 1);
+
+    for tile in (0..ROOM_AREA).map(Tile::from_index) {
+        if !terrain.is_walkable(tile) {
+            continue;
+        }
+
+        let capacity = if protected.contains(&tile) || tile.is_edge() || near_exit(tile) {
+            UNCUTTABLE
+        } else {
+            cost(tile).clamp(1, UNCUTTABLE - 1)
+        };
+        graph.add_edge(tile_in(tile), tile_out(tile), capacity);
+
+        if protected.contains(&tile) {
+            graph.add_edge(source, tile_in(tile), UNCUTTABLE);
+        }
+        if tile.is_edge() {
+            graph.add_edge(tile_out(tile), sink, UNCUTTABLE);
+        }
+        for neighbor in tile.neighbors().filter(|&n| terrain.is_walkable(n)) {
+            graph.add_edge(tile_out(tile), tile_in(neighbor), UNCUTTABLE);
+        }
+    }
+
+    if graph.max_flow(source, sink) >= UNCUTTABLE {
+        return None;
+    }
+
+    let reachable = graph.reachable(source);
+    let cut = (0..ROOM_AREA)
+        .map(Tile::from_index)
+        .filter(|&tile| reachable[tile_in(tile)] && !reachable[tile_out(tile)])
+        .collect();
+    Some(cut)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FlowEdge {
+    to: usize,
+    capacity: u32,
+}
+
+/// a flow network solved with Dinic's algorithm. every edge is stored next to its reverse edge,
+/// so the reverse of edge `i` is always `i ^ 1`.
+struct FlowGraph {
+    edges: Vec<FlowEdge>,
+    adjacent: Vec<Vec<usize>>,
+    level: Vec<u32>,
+    next_edge: Vec<usize>,
+}
+
+impl FlowGraph {
+    fn new(nodes: usize) -> Self {
+        Self {
+            edges: Vec::new(),
+            adjacent: vec![Vec::new(); nodes],
+            level: vec![0; nodes],
+            next_edge: vec![0; nodes],
+        }
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize, capacity: u32) {
+        self.adjacent[from].push(self.edges.len());
+        self.edges.push(FlowEdge { to, capacity });
+        self.adjacent[to].push(self.edges.len());
+        self.edges.push(FlowEdge {
+            to: from,
+            capacity: 0,
+        });
+    }
+
+    /// labels every node with its distance from `source` in the residual graph, returning
+    /// whether `sink` can still be reached
+    fn build_levels(&mut self, source: usize, sink: usize) -> bool {
+        self.level.fill(u32::MAX);
+        self.level[source] = 0;
+        let mut queue = VecDeque::from([source]);
+        while let Some(node) = queue.pop_front() {
+            for &edge in &self.adjacent[node] {
+                let FlowEdge { to, capacity } = self.edges[edge];
+                if capacity > 0 && self.level[to] == u32::MAX {
+                    self.level[to] = self.level[node] + 1;
+                    queue.push_back(to);
+                }
+            }
+        }
+        self.level[sink] != u32::MAX
+    }
+
+    /// pushes up to `limit` flow from `node` to `sink` along edges that go one level deeper
+    fn push(&mut self, node: usize, sink: usize, limit: u32) -> u32 {
+        if node == sink {
+            return limit;
+        }
+
+        while self.next_edge[node] < self.adjacent[node].len() {
+            let edge = self.adjacent[node][self.next_edge[node]];
+            let FlowEdge { to, capacity } = self.edges[edge];
+            if capacity > 0 && self.level[to] == self.level[node] + 1 {
+                let pushed = self.push(to, sink, limit.min(capacity));
+                if pushed > 0 {
+                    self.edges[edge].capacity -= pushed;
+                    self.edges[edge ^ 1].capacity += pushed;
+                    return pushed;
+                }
+            }
+            self.next_edge[node] += 1;
+        }
+        0
+    }
+
+    /// the maximum flow from `source` to `sink`, stopping early once it reaches [`UNCUTTABLE`]
+    fn max_flow(&mut self, source: usize, sink: usize) -> u32 {
+        let mut flow = 0;
+        while flow < UNCUTTABLE && self.build_levels(source, sink) {
+            self.next_edge.fill(0);
+            while flow < UNCUTTABLE {
+                let pushed = self.push(source, sink, UNCUTTABLE);
+                if pushed == 0 {
+                    break;
+                }
+                flow = flow.saturating_add(pushed);
+            }
+        }
+        flow
+    }
+
+    /// every node that can be reached from `source` in the residual graph
+    fn reachable(&self, source: usize) -> Vec<bool> {
+        let mut reached = vec![false; self.adjacent.len()];
+        reached[source] = true;
+        let mut queue = VecDeque::from([source]);
+        while let Some(node) = queue.pop_front() {
+            for &edge in &self.adjacent[node] {
+                let FlowEdge { to, capacity } = self.edges[edge];
+                if capacity > 0 && !reached[to] {
+                    reached[to] = true;
+                    queue.push_back(to);
+                }
+            }
+        }
+        reached
+    }
+}
+
+/// a position in W1N1, for tests that need positions rather than tiles
+#[cfg(test)]
+pub fn test_pos(x: u8, y: u8) -> Position {
+    use screeps::{RoomCoordinate, RoomName};
+
+    let room_name = "W1N1".parse::<RoomName>().unwrap();
+    Position::new(
+        RoomCoordinate::new(x).unwrap(),
+        RoomCoordinate::new(y).unwrap(),
+        room_name,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a room with walls only on the tiles where `wall` is true, so every other edge tile is an
+    /// exit
+    fn grid(wall: impl Fn(Tile) -> bool) -> TerrainGrid {
+        let raw = (0..ROOM_AREA)
+            .map(Tile::from_index)
+            .map(|tile| u8::from(wall(tile)))
+            .collect::<Vec<_>>();
+        TerrainGrid::from_raw(&raw).unwrap()
+    }
+
+    #[test]
+    fn terrain_string_is_validated() {
+        assert_eq!(
+            TerrainGrid::from_terrain_string("012").unwrap_err(),
+            TerrainError::InvalidLength(3)
+        );
+        assert_eq!(
+            TerrainGrid::from_terrain_string("0a").unwrap_err(),
+            TerrainError::InvalidChar('a')
+        );
+
+        let terrain = TerrainGrid::from_terrain_string(&"3".repeat(ROOM_AREA)).unwrap();
+        assert_eq!(terrain.get(Tile::new(7, 7)), TileTerrain::Wall);
+    }
+
+    #[test]
+    fn distance_transform_counts_to_walls_and_edges() {
+        let dist = distance_transform(&grid(|tile| tile == Tile::new(10, 10)));
+        let at = |x, y| dist[Tile::new(x, y).index()];
+
+        assert_eq!(at(0, 5), 0);
+        assert_eq!(at(3, 20), 3);
+        assert_eq!(at(40, 25), 9);
+        assert_eq!(at(25, 25), 15);
+        assert_eq!(at(10, 10), 0);
+        assert_eq!(at(11, 11), 1);
+        assert_eq!(at(12, 10), 2);
+    }
+
+    #[test]
+    fn flood_fill_stops_at_walls_and_impassable_tiles() {
+        let terrain = grid(|tile| tile.x == 20);
+
+        let reached = flood_fill(&terrain, &[Tile::new(5, 5)], |_| true);
+        assert!(reached[Tile::new(19, 40).index()]);
+        assert!(!reached[Tile::new(20, 5).index()]);
+        assert!(!reached[Tile::new(21, 5).index()]);
+
+        let reached = flood_fill(&terrain, &[Tile::new(5, 5)], |tile| tile.x != 10);
+        assert!(reached[Tile::new(9, 5).index()]);
+        assert!(!reached[Tile::new(11, 5).index()]);
+    }
+
+    #[test]
+    fn exits_reach_everything_that_is_not_blocked_off() {
+        let center = Tile::new(25, 25);
+        let blocked = ring(center, 1).collect::<HashSet<_>>();
+        let reached = reachable_from_exits(&grid(|_| false), &blocked);
+
+        assert!(reached[Tile::new(0, 0).index()]);
+        assert!(reached[Tile::new(25, 23).index()]);
+        assert!(!reached[Tile::new(25, 24).index()]);
+        assert!(!reached[center.index()]);
+
+        // walls work just as well as blocked tiles
+        let reached = reachable_from_exits(&grid(|tile| blocked.contains(&tile)), &HashSet::new());
+        assert!(!reached[center.index()]);
+    }
+
+    #[test]
+    fn open_areas_are_split_by_walls() {
+        let areas = open_areas(&grid(|tile| tile.x == 25), 3);
+
+        // x from 3 to 22 on the left and 28 to 46 on the right, y from 3 to 46 on both
+        assert_eq!(areas.len(), 2);
+        assert_eq!(areas[0].len(), 20 * 44);
+        assert_eq!(areas[1].len(), 19 * 44);
+        assert!(areas[0].contains(&Tile::new(3, 3)));
+        assert!(areas[1].contains(&Tile::new(46, 46)));
+        assert!(!areas[0].contains(&Tile::new(2, 3)));
+    }
+
+    #[test]
+    fn walking_distance_goes_around_walls() {
+        let open = walking_distance(&grid(|_| false), &[Tile::new(10, 10)]);
+        assert_eq!(open[Tile::new(13, 12).index()], 3);
+
+        // the only way past the wall is through (12, 31), 21 moves away from both tiles
+        let terrain = grid(|tile| tile.x == 12 && tile.y <= 30);
+        let distance = walking_distance(&terrain, &[Tile::new(10, 10)]);
+        assert_eq!(distance[Tile::new(14, 10).index()], 42);
+
+        let enclosed = grid(|tile| tile.range_to(Tile::new(40, 40)) == 1);
+        let distance = walking_distance(&enclosed, &[Tile::new(10, 10)]);
+        assert_eq!(distance[Tile::new(40, 40).index()], u32::MAX);
+    }
+
+    #[test]
+    fn min_cut_surrounds_a_single_tile() {
+        let center = Tile::new(25, 25);
+        let cut = min_cut(&grid(|_| false), &[center], |_| 1).unwrap();
+
+        assert_eq!(cut.len(), 8);
+        assert!(cut.iter().all(|tile| tile.range_to(center) == 1));
+    }
+
+    #[test]
+    fn min_cut_blocks_the_entrance_of_an_enclosure() {
+        // a walled square from 10 to 40 with a 3 tile gap in the middle of its top side
+        let terrain = grid(|tile| {
+            let on_border = (10..=40).contains(&tile.x)
+                && (10..=40).contains(&tile.y)
+                && (tile.x == 10 || tile.x == 40 || tile.y == 10 || tile.y == 40);
+            on_border && !(tile.y == 10 && (24..=26).contains(&tile.x))
+        });
+        let cut = min_cut(&terrain, &[Tile::new(25, 25)], |_| 1).unwrap();
+
+        assert_eq!(
+            cut,
+            vec![Tile::new(24, 10), Tile::new(25, 10), Tile::new(26, 10)]
+        );
+    }
+
+    #[test]
+    fn min_cut_prefers_cheap_tiles() {
+        let center = Tile::new(25, 25);
+        // the ring at range 2 is cheaper in total than the ring at range 1
+        let cost = |tile: Tile| if tile.range_to(center) == 1 { 10 } else { 1 };
+        let cut = min_cut(&grid(|_| false), &[center], cost).unwrap();
+
+        assert_eq!(cut.len(), 16);
+        assert!(cut.iter().all(|tile| tile.range_to(center) == 2));
+    }
+
+    #[test]
+    fn min_cut_fails_next_to_exits() {
+        assert_eq!(min_cut(&grid(|_| false), &[Tile::new(2, 25)], |_| 1), None);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::stats::GlobalStats;
+use crate::{colony::spawning::SpawnQueue, stats::GlobalStats};
 use log::{debug, info, trace};
 use screeps::{game, RoomName, RoomVisual, TextAlign, TextStyle, CPU_BUCKET_MAX};
 
@@ -6,6 +6,7 @@ pub struct UiVisualizer {
     room: Option<RoomName>,
     visual: RoomVisual,
     line: u32,
+    column: f32,
 }
 
 impl UiVisualizer {
@@ -15,9 +16,16 @@ impl UiVisualizer {
             room,
             visual: RoomVisual::new(room),
             line: 0,
+            column: 0.0,
         }
     }
 
+    /// draws text starting at column `x` instead of the left edge of the room
+    pub fn with_column(mut self, x: f32) -> Self {
+        self.column = x;
+        self
+    }
+
     pub fn draw_stats(&mut self, stats: &GlobalStats) {
         // Track how long was spent showing the stats
         let start_cpu = game::cpu::get_used();
@@ -67,6 +75,33 @@ impl UiVisualizer {
         info!("Used {:>1.3}cpu for visualizer UI", end_cpu - start_cpu,);
     }
 
+    pub fn draw_spawn_queue(&mut self, colony_name: &str, queue: &SpawnQueue) {
+        let style = Some(
+            TextStyle::default()
+                .align(TextAlign::Left)
+                .custom_font("0.5 monospace"),
+        );
+
+        self.draw_line(format!("{} spawn queue:", colony_name), style.clone());
+        if queue.is_empty() {
+            self.draw_line(String::from("  (empty)"), style.clone());
+            return;
+        }
+
+        for request in queue.iter() {
+            self.draw_line(
+                format!(
+                    "  {:?} {:?} {}e {} parts",
+                    request.priority(),
+                    request.role(),
+                    request.cost(),
+                    request.body().len()
+                ),
+                style.clone(),
+            );
+        }
+    }
+
     fn draw_cpu(&mut self, cpu_usage: f64, cpu_limit: u32, style: Option<TextStyle>) {
         let cpu_percent = cpu_usage / (cpu_limit as f64);
         let color_str = if cpu_percent > 1.0 {
@@ -94,7 +129,7 @@ impl UiVisualizer {
 
     fn draw_line(&mut self, text: String, style: Option<TextStyle>) {
         let y_offset = self.line as f32 + 0.15_f32;
-        self.visual.text(0.0, y_offset, text, style);
+        self.visual.text(self.column, y_offset, text, style);
         self.line += 1;
     }
 